@@ -0,0 +1,141 @@
+use crate::nip59::{open_gift_wrap, open_seal};
+use nip44::v2::ConversationKey;
+use nostr_sdk::event::builder::Error as BuilderError;
+use nostr_sdk::prelude::*;
+use nostr_sdk::util::generate_shared_key;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// NIP-44 conversation keys between our keys and the people we talk to
+///
+/// Deriving a key takes an ECDH, we keep them so Mostro and the peers of our
+/// trades only cost one each per key of ours.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationKeys {
+    keys: Arc<RwLock<HashMap<(PublicKey, PublicKey), ConversationKey>>>,
+    /// Shared keys of our trade chats by their public key
+    chats: Arc<RwLock<HashMap<PublicKey, SharedChat>>>,
+}
+
+/// Chat between one of our trade keys and the counterparty of the trade
+#[derive(Debug, Clone)]
+pub struct SharedChat {
+    pub keys: Keys,
+    pub trade_pubkey: PublicKey,
+    pub peer: PublicKey,
+}
+
+impl ConversationKeys {
+    /// Conversation key between `keys` and `counterparty`, derived the first time
+    pub fn get(&self, keys: &Keys, counterparty: &PublicKey) -> ConversationKey {
+        let id = (keys.public_key(), *counterparty);
+        if let Some(ck) = self.keys.read().unwrap().get(&id) {
+            return *ck;
+        }
+        let ck = ConversationKey::derive(keys.secret_key(), counterparty);
+        self.keys.write().unwrap().insert(id, ck);
+
+        ck
+    }
+
+    /// Unwraps a gift wrap sent to `keys` using the cached key of the seal author
+    pub fn unwrap_gift_wrap(
+        &self,
+        keys: &Keys,
+        gift_wrap: &Event,
+    ) -> Result<UnwrappedGift, BuilderError> {
+        // Every gift wrap is signed by a new key, there is nothing to reuse
+        let gw_ck = ConversationKey::derive(keys.secret_key(), &gift_wrap.pubkey);
+        let seal = open_gift_wrap(&gw_ck, gift_wrap)?;
+        let seal_ck = self.get(keys, &seal.pubkey);
+
+        open_seal(&seal_ck, seal)
+    }
+
+    /// Keys of the chat between `trade_keys` and `counterparty`
+    ///
+    /// Both sides get the same keys from the ECDH of their trade keys, chat
+    /// messages are wrapped to its public key as Mostro clients do,
+    /// https://mostro.network/protocol/chat.html
+    pub fn shared_keys(&self, trade_keys: &Keys, counterparty: &PublicKey) -> Result<Keys> {
+        let secret_key =
+            SecretKey::from_slice(&generate_shared_key(trade_keys.secret_key(), counterparty))?;
+        let keys = Keys::new(secret_key);
+        self.chats.write().unwrap().insert(
+            keys.public_key(),
+            SharedChat {
+                keys: keys.clone(),
+                trade_pubkey: trade_keys.public_key(),
+                peer: *counterparty,
+            },
+        );
+
+        Ok(keys)
+    }
+
+    /// Chat whose shared key is `pubkey`, if it is one of ours
+    pub fn chat(&self, pubkey: &PublicKey) -> Option<SharedChat> {
+        self.chats.read().unwrap().get(pubkey).cloned()
+    }
+
+    /// Shared key of a trade's chat in hex, to hand to the solver of a dispute
+    ///
+    /// It opens the messages of this chat and nothing else, so the solver
+    /// doesn't get any of our private keys.
+    pub fn export(&self, trade_keys: &Keys, counterparty: &PublicKey) -> Result<String> {
+        Ok(self
+            .shared_keys(trade_keys, counterparty)?
+            .secret_key()
+            .to_secret_hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nip59::private_message;
+
+    #[test]
+    fn both_sides_share_the_chat_keys() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let keys = ConversationKeys::default();
+
+        let alice_side = keys.shared_keys(&alice, &bob.public_key()).unwrap();
+        let bob_side = keys.shared_keys(&bob, &alice.public_key()).unwrap();
+
+        assert_eq!(alice_side.public_key(), bob_side.public_key());
+    }
+
+    #[test]
+    fn exported_key_opens_the_chat_messages() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let alice_keys = ConversationKeys::default();
+        let shared = alice_keys.shared_keys(&alice, &bob.public_key()).unwrap();
+        let event = private_message(&bob, shared.public_key(), "paid".to_string()).unwrap();
+
+        let exported = alice_keys.export(&alice, &bob.public_key()).unwrap();
+        let solver_keys = Keys::parse(&exported).unwrap();
+        let unwrapped = ConversationKeys::default()
+            .unwrap_gift_wrap(&solver_keys, &event)
+            .unwrap();
+
+        assert_eq!(unwrapped.rumor.content, "paid");
+        assert_eq!(unwrapped.sender, bob.public_key());
+        assert_eq!(unwrapped.rumor.pubkey, bob.public_key());
+    }
+
+    #[test]
+    fn exported_key_opens_only_its_chat() {
+        let (alice, bob, carol) = (Keys::generate(), Keys::generate(), Keys::generate());
+        let keys = ConversationKeys::default();
+        let other = keys.shared_keys(&carol, &bob.public_key()).unwrap();
+        let event = private_message(&bob, other.public_key(), "paid".to_string()).unwrap();
+
+        let exported = keys.export(&alice, &bob.public_key()).unwrap();
+        let solver_keys = Keys::parse(&exported).unwrap();
+
+        assert!(ConversationKeys::default()
+            .unwrap_gift_wrap(&solver_keys, &event)
+            .is_err());
+    }
+}
@@ -1,5 +1,8 @@
-use crate::settings::get_settings_path;
+use crate::identity::FIRST_TRADE_INDEX;
+use crate::settings::{get_settings_path, TradingMode};
+use mostro_core::order::SmallOrder;
 use sqlx::pool::Pool;
+use sqlx::FromRow;
 use sqlx::Sqlite;
 use sqlx::SqlitePool;
 use std::fs::File;
@@ -10,47 +13,85 @@ pub async fn connect() -> Result<Pool<Sqlite>, sqlx::Error> {
     let mostrui_db_path = format!("{}/mostrui.db", mostrui_dir);
 
     if !Path::exists(Path::new(&mostrui_db_path)) {
-        if let Err(res) = File::create(&mostrui_db_path) {
-            println!("Error in creating db file: {}", res)
-        }
+        File::create(&mostrui_db_path)?;
     }
 
     let db_url = format!("sqlite://{}", mostrui_db_path);
     let pool = SqlitePool::connect(&db_url).await?;
-
-    // We create the database file with orders table if the file doesn't exists
-    if !Path::new(&mostrui_db_path).exists() {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS orders (
-                id TEXT PRIMARY KEY,
-                kind TEXT,
-                status TEXT,
-                amount INTEGER NOT NULL,
-                fiat_code TEXT NOT NULL,
-                min_amount INTEGER,
-                max_amount INTEGER,
-                fiat_amount INTEGER NOT NULL,
-                payment_method TEXT NOT NULL,
-                premium INTEGER NOT NULL,
-                master_buyer_pubkey TEXT,
-                master_seller_pubkey TEXT,
-                buyer_invoice TEXT,
-                created_at INTEGER,
-                expires_at INTEGER,
-                buyer_token INTEGER,
-                seller_token INTEGER
-            );
-            "#,
-        )
-        .execute(&pool)
-        .await?;
-    }
+    create_tables(&pool).await?;
 
     Ok(pool)
 }
 
-#[derive(Debug, Default, Clone)]
+/// Creates the tables if they don't exist yet
+pub async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+          CREATE TABLE IF NOT EXISTS orders (
+              id TEXT PRIMARY KEY,
+              kind TEXT,
+              status TEXT,
+              amount INTEGER NOT NULL,
+              fiat_code TEXT NOT NULL,
+              min_amount INTEGER,
+              max_amount INTEGER,
+              fiat_amount INTEGER NOT NULL,
+              payment_method TEXT NOT NULL,
+              premium INTEGER NOT NULL,
+              master_buyer_pubkey TEXT,
+              master_seller_pubkey TEXT,
+              buyer_invoice TEXT,
+              created_at INTEGER,
+              expires_at INTEGER,
+              buyer_token INTEGER,
+              seller_token INTEGER
+          );
+          CREATE TABLE IF NOT EXISTS trade_keys (
+              trade_index INTEGER PRIMARY KEY,
+              order_id TEXT UNIQUE,
+              role TEXT NOT NULL,
+              trading_mode TEXT NOT NULL,
+              created_at INTEGER NOT NULL
+          );
+          CREATE TABLE IF NOT EXISTS restores (
+              pubkey TEXT PRIMARY KEY,
+              restored_at INTEGER NOT NULL
+          );
+          CREATE TABLE IF NOT EXISTS ratings (
+              order_id TEXT PRIMARY KEY,
+              rating INTEGER NOT NULL,
+              created_at INTEGER NOT NULL
+          );
+          CREATE TABLE IF NOT EXISTS transitions (
+              order_id TEXT NOT NULL,
+              action TEXT NOT NULL,
+              from_status TEXT NOT NULL,
+              to_status TEXT NOT NULL,
+              accepted INTEGER NOT NULL,
+              created_at INTEGER NOT NULL
+          );
+          CREATE TABLE IF NOT EXISTS messages (
+              id TEXT PRIMARY KEY,
+              rumor_id TEXT,
+              sender TEXT NOT NULL,
+              receiver TEXT NOT NULL,
+              order_id TEXT,
+              action TEXT,
+              content TEXT NOT NULL,
+              kind INTEGER NOT NULL,
+              verified INTEGER NOT NULL,
+              created_at INTEGER NOT NULL,
+              read INTEGER NOT NULL DEFAULT 0
+          );
+          "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, PartialEq, FromRow)]
 pub struct Order {
     pub id: Option<String>,
     pub kind: Option<String>,
@@ -71,6 +112,30 @@ pub struct Order {
     pub seller_token: Option<u16>,
 }
 
+impl From<&SmallOrder> for Order {
+    fn from(order: &SmallOrder) -> Self {
+        Self {
+            id: order.id.map(|id| id.to_string()),
+            kind: order.kind.map(|k| k.to_string()),
+            status: order.status.map(|s| s.to_string()),
+            amount: order.amount,
+            fiat_code: order.fiat_code.clone(),
+            min_amount: order.min_amount,
+            max_amount: order.max_amount,
+            fiat_amount: order.fiat_amount,
+            payment_method: order.payment_method.clone(),
+            premium: order.premium,
+            master_buyer_pubkey: order.master_buyer_pubkey.clone(),
+            master_seller_pubkey: order.master_seller_pubkey.clone(),
+            buyer_invoice: order.buyer_invoice.clone(),
+            created_at: order.created_at,
+            expires_at: order.expires_at,
+            buyer_token: order.buyer_token,
+            seller_token: order.seller_token,
+        }
+    }
+}
+
 impl Order {
     // Setters encadenables
     pub fn set_kind(&mut self, kind: String) -> &mut Self {
@@ -118,6 +183,62 @@ impl Order {
         self
     }
 
+    // Inserting the order, replacing it if we already had it
+    pub async fn create(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+              INSERT OR REPLACE INTO orders (id, kind, status, amount, fiat_code, min_amount,
+                  max_amount, fiat_amount, payment_method, premium, master_buyer_pubkey,
+                  master_seller_pubkey, buyer_invoice, created_at, expires_at, buyer_token,
+                  seller_token)
+              VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+              "#,
+        )
+        .bind(&self.id)
+        .bind(&self.kind)
+        .bind(&self.status)
+        .bind(self.amount)
+        .bind(&self.fiat_code)
+        .bind(self.min_amount)
+        .bind(self.max_amount)
+        .bind(self.fiat_amount)
+        .bind(&self.payment_method)
+        .bind(self.premium)
+        .bind(&self.master_buyer_pubkey)
+        .bind(&self.master_seller_pubkey)
+        .bind(&self.buyer_invoice)
+        .bind(self.created_at)
+        .bind(self.expires_at)
+        .bind(self.buyer_token)
+        .bind(self.seller_token)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn by_id(pool: &SqlitePool, id: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM orders WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn all(pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM orders ORDER BY created_at DESC")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn delete(pool: &SqlitePool, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM orders WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
     // Applying changes to the database
     pub async fn save(&self, pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
         // Validation if an identity document is present
@@ -151,12 +272,341 @@ impl Order {
             .bind(id)
             .execute(pool)
             .await?;
-
-            println!("Order with id {} updated in the database.", id);
         } else {
-            println!("Order must have an ID to be updated.");
+            return Err("Order must have an ID to be updated".into());
         }
 
         Ok(())
     }
 }
+
+/// Derivation index of the keys used in a trade
+///
+/// https://mostro.network/protocol/key_management.html
+#[derive(Debug, Clone, FromRow)]
+pub struct TradeKey {
+    pub trade_index: i64,
+    pub order_id: Option<String>,
+    /// `maker` or `taker`
+    pub role: String,
+    /// `reputation` or `privacy`, see `TradingMode`
+    pub trading_mode: String,
+    pub created_at: i64,
+}
+
+impl TradeKey {
+    pub fn new(
+        trade_index: i64,
+        order_id: Option<String>,
+        role: &str,
+        trading_mode: TradingMode,
+    ) -> Self {
+        Self {
+            trade_index,
+            order_id,
+            role: role.to_string(),
+            trading_mode: trading_mode.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// Returns the first index that wasn't used by any trade
+    pub async fn next_index(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+        let last: Option<i64> = sqlx::query_scalar("SELECT MAX(trade_index) FROM trade_keys")
+            .fetch_one(pool)
+            .await?;
+
+        Ok(last.map_or(FIRST_TRADE_INDEX as i64, |i| i + 1))
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+              INSERT INTO trade_keys (trade_index, order_id, role, trading_mode, created_at)
+              VALUES (?, ?, ?, ?, ?)
+              "#,
+        )
+        .bind(self.trade_index)
+        .bind(&self.order_id)
+        .bind(&self.role)
+        .bind(&self.trading_mode)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Links a trade key created before Mostro assigned an id to the order
+    pub async fn set_order_id(
+        pool: &SqlitePool,
+        trade_index: i64,
+        order_id: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE trade_keys SET order_id = ? WHERE trade_index = ?")
+            .bind(order_id)
+            .bind(trade_index)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Unlinks the trade key from an order we failed to take
+    ///
+    /// The index stays used, keys are never reused for another trade.
+    pub async fn release(pool: &SqlitePool, order_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE trade_keys SET order_id = NULL WHERE order_id = ?")
+            .bind(order_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn by_order_id(
+        pool: &SqlitePool,
+        order_id: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM trade_keys WHERE order_id = ?")
+            .bind(order_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn all(pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM trade_keys ORDER BY trade_index")
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Forgets every trade key, they were derived from a mnemonic we replaced,
+    /// with the orders, transitions, messages and ratings of their trades
+    ///
+    /// Everything is deleted in one transaction so no trade is left without its key.
+    pub async fn delete_all(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let ours = "SELECT order_id FROM trade_keys WHERE order_id IS NOT NULL";
+        let mut tx = pool.begin().await?;
+        for (table, column) in [
+            ("transitions", "order_id"),
+            ("messages", "order_id"),
+            ("ratings", "order_id"),
+            ("orders", "id"),
+        ] {
+            sqlx::query(&format!(
+                "DELETE FROM {} WHERE {} IN ({})",
+                table, column, ours
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("DELETE FROM trade_keys")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Mnemonic whose trades we already restored, by the public key of its
+/// first trade key
+#[derive(Debug, Clone, FromRow)]
+pub struct Restore {
+    pub pubkey: String,
+    pub restored_at: i64,
+}
+
+impl Restore {
+    pub fn new(pubkey: String) -> Self {
+        Self {
+            pubkey,
+            restored_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR REPLACE INTO restores (pubkey, restored_at) VALUES (?, ?)")
+            .bind(&self.pubkey)
+            .bind(self.restored_at)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn by_pubkey(pool: &SqlitePool, pubkey: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM restores WHERE pubkey = ?")
+            .bind(pubkey)
+            .fetch_optional(pool)
+            .await
+    }
+}
+
+/// Rating we gave to the counterparty of a trade
+#[derive(Debug, Clone, FromRow)]
+pub struct Rating {
+    pub order_id: String,
+    pub rating: u8,
+    pub created_at: i64,
+}
+
+impl Rating {
+    pub fn new(order_id: String, rating: u8) -> Self {
+        Self {
+            order_id,
+            rating,
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO ratings (order_id, rating, created_at) VALUES (?, ?, ?)",
+        )
+        .bind(&self.order_id)
+        .bind(self.rating)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn by_order_id(
+        pool: &SqlitePool,
+        order_id: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM ratings WHERE order_id = ?")
+            .bind(order_id)
+            .fetch_optional(pool)
+            .await
+    }
+}
+
+/// Message Mostro sent about one of our trades and what it did to its status
+#[derive(Debug, Clone, FromRow)]
+pub struct Transition {
+    pub order_id: String,
+    /// Action of the message, kebab-case as in the protocol
+    pub action: String,
+    pub from_status: String,
+    pub to_status: String,
+    /// False if the message made no sense in `from_status` and was flagged
+    pub accepted: bool,
+    /// When Mostro sent the message
+    pub created_at: i64,
+}
+
+impl Transition {
+    pub async fn create(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+              INSERT INTO transitions (order_id, action, from_status, to_status, accepted,
+                  created_at)
+              VALUES (?, ?, ?, ?, ?, ?)
+              "#,
+        )
+        .bind(&self.order_id)
+        .bind(&self.action)
+        .bind(&self.from_status)
+        .bind(&self.to_status)
+        .bind(self.accepted)
+        .bind(self.created_at)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Transitions of a trade, oldest first
+    pub async fn by_order_id(pool: &SqlitePool, order_id: &str) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>(
+            "SELECT * FROM transitions WHERE order_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(order_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Transitions of all our trades, oldest first
+    pub async fn all(pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM transitions ORDER BY created_at, rowid")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn delete_by_order_id(pool: &SqlitePool, order_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM transitions WHERE order_id = ?")
+            .bind(order_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Decrypted message we got or sent on one of our keys
+#[derive(Debug, Clone, FromRow)]
+pub struct Message {
+    /// Id of the gift wrap that carried it
+    pub id: String,
+    pub rumor_id: Option<String>,
+    pub sender: String,
+    pub receiver: String,
+    pub order_id: Option<String>,
+    /// Action of Mostro messages, kebab-case as in the protocol, None for chat messages
+    pub action: Option<String>,
+    /// Content of the rumor as it was sent
+    pub content: String,
+    /// Kind of the rumor
+    pub kind: u16,
+    /// True if the rumor author signed the seal
+    pub verified: bool,
+    pub created_at: i64,
+    pub read: bool,
+}
+
+impl Message {
+    /// Saves the message, returns false if we already had it
+    pub async fn create(&self, pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+              INSERT OR IGNORE INTO messages (id, rumor_id, sender, receiver, order_id, action,
+                  content, kind, verified, created_at, read)
+              VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+              "#,
+        )
+        .bind(&self.id)
+        .bind(&self.rumor_id)
+        .bind(&self.sender)
+        .bind(&self.receiver)
+        .bind(&self.order_id)
+        .bind(&self.action)
+        .bind(&self.content)
+        .bind(self.kind)
+        .bind(self.verified)
+        .bind(self.created_at)
+        .bind(self.read)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// All the messages, newest first
+    pub async fn all(pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as::<_, Self>("SELECT * FROM messages ORDER BY created_at DESC, rowid DESC")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn mark_read(pool: &SqlitePool, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE messages SET read = 1 WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
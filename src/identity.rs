@@ -0,0 +1,102 @@
+use nostr_sdk::prelude::rand::rngs::OsRng;
+use nostr_sdk::prelude::rand::RngCore;
+use nostr_sdk::prelude::*;
+
+/// Account used by Mostro clients in the NIP-06 derivation path
+/// `m/44'/1237'/38383'/0/<index>`
+///
+/// https://mostro.network/protocol/key_management.html
+const MOSTRO_ACCOUNT: u32 = 38383;
+
+/// Index 0 is reserved for the identity key, trade keys start at 1
+pub const FIRST_TRADE_INDEX: u32 = 1;
+
+/// User identity backed by a BIP-39 mnemonic
+///
+/// The identity key can be replaced by an imported nsec, trade keys are
+/// always derived from the mnemonic. Solvers also keep their key here.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    mnemonic: Mnemonic,
+    identity_key: Option<SecretKey>,
+    admin_key: Option<SecretKey>,
+}
+
+impl Identity {
+    /// Generates a new identity with a random 12 words mnemonic
+    pub fn generate() -> Result<Self> {
+        let mut entropy = [0u8; 16];
+        OsRng.fill_bytes(&mut entropy);
+        let mnemonic = Mnemonic::from_entropy(&entropy)?;
+
+        Ok(Self {
+            mnemonic,
+            identity_key: None,
+            admin_key: None,
+        })
+    }
+
+    /// Imports an identity from an existing mnemonic
+    pub fn from_mnemonic(words: &str) -> Result<Self> {
+        let mnemonic = Mnemonic::parse_normalized(words.trim())?;
+
+        Ok(Self {
+            mnemonic,
+            identity_key: None,
+            admin_key: None,
+        })
+    }
+
+    /// Uses an imported secret key as identity instead of the derived one
+    pub fn with_identity_key(mut self, secret_key: SecretKey) -> Self {
+        self.identity_key = Some(secret_key);
+        self
+    }
+
+    pub fn imported_identity_key(&self) -> Option<&SecretKey> {
+        self.identity_key.as_ref()
+    }
+
+    /// Uses `secret_key` as the solver key in admin mode
+    pub fn with_admin_key(mut self, secret_key: SecretKey) -> Self {
+        self.admin_key = Some(secret_key);
+        self
+    }
+
+    pub fn admin_key(&self) -> Option<&SecretKey> {
+        self.admin_key.as_ref()
+    }
+
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    /// Keys used to build reputation, derived at index 0 unless one was imported
+    pub fn identity_keys(&self) -> Result<Keys> {
+        match &self.identity_key {
+            Some(secret_key) => Ok(Keys::new(secret_key.clone())),
+            None => self.derive(0),
+        }
+    }
+
+    /// Keys used for a single trade, derived at `index`
+    pub fn trade_keys(&self, index: u32) -> Result<Keys> {
+        if index < FIRST_TRADE_INDEX {
+            return Err("Trade keys must be derived from index 1 onwards".into());
+        }
+        self.derive(index)
+    }
+
+    fn derive(&self, index: u32) -> Result<Keys> {
+        let mnemonic = self.mnemonic();
+        let keys = Keys::from_mnemonic_advanced(
+            mnemonic.as_str(),
+            None,
+            Some(MOSTRO_ACCOUNT),
+            Some(0),
+            Some(index),
+        )?;
+
+        Ok(keys)
+    }
+}
@@ -0,0 +1,218 @@
+use crate::identity::Identity;
+use crate::settings::get_settings_path;
+use base64::engine::{general_purpose, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use nostr_sdk::prelude::rand::RngCore;
+use nostr_sdk::prelude::*;
+use scrypt::Params;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+const KEYSTORE_VERSION: u8 = 1;
+/// Same scrypt cost NIP-49 uses by default
+const LOG_N: u8 = 16;
+const SALT_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+
+/// Secrets stored encrypted in the keystore file
+#[derive(Debug, Serialize, Deserialize)]
+struct Secrets {
+    mnemonic: String,
+    /// Imported identity key in bech32
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity_nsec: Option<String>,
+    /// Solver key in bech32, only used in admin mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    admin_nsec: Option<String>,
+}
+
+/// Keystore file content, binary fields are base64 encoded
+#[derive(Debug, Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u8,
+    log_n: u8,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Passphrase protected storage for the user identity and the solver key
+///
+/// The secrets are encrypted with XChaCha20-Poly1305 using a key derived
+/// from the passphrase with scrypt, the same scheme NIP-49 uses for ncryptsec.
+#[derive(Clone)]
+pub struct Keystore {
+    passphrase: String,
+}
+
+impl std::fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keystore").finish_non_exhaustive()
+    }
+}
+
+impl Keystore {
+    pub fn new(passphrase: &str) -> Self {
+        Self {
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    pub fn exists() -> bool {
+        Path::new(&keystore_path()).exists()
+    }
+
+    /// Decrypts the identity stored in the keystore file
+    pub fn load(&self) -> Result<Identity> {
+        self.decrypt(&fs::read_to_string(keystore_path())?)
+    }
+
+    /// Encrypts the identity and writes it to the keystore file
+    ///
+    /// The content goes to a temporary file only the user can read, which then
+    /// replaces the old keystore, so a failed write never truncates it.
+    pub fn save(&self, identity: &Identity) -> Result<()> {
+        let content = self.encrypt(identity, LOG_N)?;
+        let path = keystore_path();
+        let tmp_path = path.with_extension("json.tmp");
+
+        // A file left by a crash may have other permissions, start a new one
+        if tmp_path.exists() {
+            fs::remove_file(&tmp_path)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(())
+    }
+
+    /// Keystore file content with the identity encrypted, `log_n` is the scrypt cost
+    fn encrypt(&self, identity: &Identity, log_n: u8) -> Result<String> {
+        let identity_nsec = match identity.imported_identity_key() {
+            Some(secret_key) => Some(secret_key.to_bech32()?),
+            None => None,
+        };
+        let admin_nsec = match identity.admin_key() {
+            Some(secret_key) => Some(secret_key.to_bech32()?),
+            None => None,
+        };
+        let secrets = Secrets {
+            mnemonic: identity.mnemonic(),
+            identity_nsec,
+            admin_nsec,
+        };
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let cipher = XChaCha20Poly1305::new(&self.derive_key(&salt, log_n)?.into());
+        let ciphertext = cipher
+            .encrypt(&nonce, serde_json::to_vec(&secrets)?.as_slice())
+            .map_err(|e| format!("Error encrypting keystore: {}", e))?;
+
+        let file = KeystoreFile {
+            version: KEYSTORE_VERSION,
+            log_n,
+            salt: general_purpose::STANDARD.encode(salt),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        };
+
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    /// Decrypts the identity in the content of a keystore file
+    fn decrypt(&self, content: &str) -> Result<Identity> {
+        let file: KeystoreFile = serde_json::from_str(content)?;
+        if file.version != KEYSTORE_VERSION {
+            return Err(format!("Unsupported keystore version {}", file.version).into());
+        }
+        let salt = general_purpose::STANDARD.decode(file.salt)?;
+        let nonce = general_purpose::STANDARD.decode(file.nonce)?;
+        let ciphertext = general_purpose::STANDARD.decode(file.ciphertext)?;
+        if nonce.len() != 24 {
+            return Err("Invalid keystore nonce".into());
+        }
+
+        let cipher = XChaCha20Poly1305::new(&self.derive_key(&salt, file.log_n)?.into());
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "Wrong passphrase")?;
+        let secrets: Secrets = serde_json::from_slice(&plaintext)?;
+
+        let mut identity = Identity::from_mnemonic(&secrets.mnemonic)?;
+        if let Some(nsec) = secrets.identity_nsec {
+            identity = identity.with_identity_key(SecretKey::from_bech32(nsec)?);
+        }
+        if let Some(nsec) = secrets.admin_nsec {
+            identity = identity.with_admin_key(SecretKey::from_bech32(nsec)?);
+        }
+
+        Ok(identity)
+    }
+
+    fn derive_key(&self, salt: &[u8], log_n: u8) -> Result<[u8; KEY_SIZE]> {
+        let params = Params::new(log_n, 8, 1, KEY_SIZE)
+            .map_err(|e| format!("Invalid scrypt params: {}", e))?;
+        let mut key = [0u8; KEY_SIZE];
+        scrypt::scrypt(self.passphrase.as_bytes(), salt, &params, &mut key)
+            .map_err(|e| format!("Error deriving keystore key: {}", e))?;
+
+        Ok(key)
+    }
+}
+
+fn keystore_path() -> PathBuf {
+    PathBuf::from(get_settings_path()).join("keystore.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Low scrypt cost so the tests run fast
+    const TEST_LOG_N: u8 = 4;
+
+    #[test]
+    fn round_trip_keeps_the_identity() {
+        let identity = Identity::generate()
+            .unwrap()
+            .with_identity_key(Keys::generate().secret_key().clone())
+            .with_admin_key(Keys::generate().secret_key().clone());
+        let keystore = Keystore::new("correct horse battery staple");
+
+        let content = keystore.encrypt(&identity, TEST_LOG_N).unwrap();
+        let loaded = keystore.decrypt(&content).unwrap();
+
+        assert_eq!(loaded.mnemonic(), identity.mnemonic());
+        assert_eq!(
+            loaded.imported_identity_key(),
+            identity.imported_identity_key()
+        );
+        assert_eq!(loaded.admin_key(), identity.admin_key());
+        assert!(!content.contains(&identity.mnemonic()));
+    }
+
+    #[test]
+    fn wrong_passphrase_fails() {
+        let identity = Identity::generate().unwrap();
+        let content = Keystore::new("correct horse battery staple")
+            .encrypt(&identity, TEST_LOG_N)
+            .unwrap();
+
+        let error = Keystore::new("wrong").decrypt(&content).unwrap_err();
+
+        assert_eq!(error.to_string(), "Wrong passphrase");
+    }
+}
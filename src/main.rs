@@ -1,14 +1,27 @@
+pub mod conversation;
 pub mod db;
+pub mod identity;
+pub mod keystore;
 pub mod nip59;
+pub mod restore;
 pub mod settings;
+pub mod trade;
 pub mod util;
 
-use crate::db::connect;
-use crate::nip59::{gift_wrap, unwrap_gift_wrap};
-use crate::settings::{get_settings_path, init_global_settings, Settings};
-use crate::util::order_from_tags;
+use crate::conversation::ConversationKeys;
+use crate::db::{connect, TradeKey};
+use crate::identity::Identity;
+use crate::keystore::Keystore;
+use crate::nip59::{gift_wrap, private_message};
+use crate::restore::{restore_session, was_restored};
+use crate::settings::{
+    get_profile, get_settings_path, init_global_settings, is_valid_profile_name, list_profiles,
+    set_profile, Settings, TradingMode, DEFAULT_PROFILE,
+};
+use crate::trade::{Applied, PendingRequest, Role, Trade};
+use crate::util::{countdown, is_valid_bolt11, normalize_invoice, order_from_tags};
 use chrono::{DateTime, Local, TimeZone};
-use mostro_core::message::{Action, Content, Message};
+use mostro_core::message::{Action, Content, Message, MessageKind};
 use mostro_core::order::{Kind as OrderKind, SmallOrder as Order, Status};
 use mostro_core::NOSTR_REPLACEABLE_EVENT_KIND;
 use nostr_sdk::prelude::*;
@@ -28,128 +41,549 @@ use ratatui::{
     },
     DefaultTerminal, Frame,
 };
+use sqlx::SqlitePool;
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::OnceLock;
 use std::{
     sync::{Arc, RwLock},
     time::Duration,
 };
+use tokio::sync::mpsc::{self, UnboundedSender};
 use tui_input::backend::crossterm::EventHandler;
 use tui_input::Input;
+use uuid::Uuid;
 mod widgets;
-use widgets::settings_widget::SettingsWidget;
-
-static SETTINGS: OnceLock<Settings> = OnceLock::new();
+use widgets::chat_widget::{ChatMessage, ChatOutcome, ChatPane, ChatWidget};
+use widgets::disputes_widget::{DisputeListWidget, DISPUTES_SUB_ID};
+use widgets::order_form_widget::{OrderFormState, OrderFormWidget};
+use widgets::pay_invoice_widget::{PayInvoice, PayInvoiceWidget};
+use widgets::profile_widget::ProfileWidget;
+use widgets::rating_widget::RatingState;
+use widgets::settings_widget::{IdentityCommand, IdentityMode, IdentityState, SettingsWidget};
+use widgets::setup_widget::{SetupOutcome, SetupWidget};
+use widgets::trades_widget::{
+    counterparty, error_text, invoice_intro, reply_text, CancelMode, ConfirmOutcome, RestoreState,
+    TradeConfirm, TradesWidget,
+};
 
-// TODO: generate keys for each order (maker or taker)
-// pubkey 000001273664dafe71d01c4541b726864bc430471f106eb48afc988ef6443a15
-const MY_PRIVATE_KEY: &str = "e02e5a36e3439b2df5172976bb58398ab2507306471c903c3820e1bcd57cd10b";
+static SETTINGS: RwLock<Option<Settings>> = RwLock::new(None);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let settings_path = get_settings_path();
-    let settings_file_path = PathBuf::from(settings_path);
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal).await;
+    ratatui::restore();
 
-    // Create config global var
-    init_global_settings(Settings::new(settings_file_path)?);
-    let terminal = ratatui::init();
-    let author = PublicKey::from_str(Settings::get().mostro_pubkey.as_str())?;
-    let app = App::new(author);
-    let _db = connect().await?;
+    result
+}
 
-    let client = Client::new(&app.my_keys);
-    let relays = Settings::get().relays.clone();
-    for relay in relays {
-        client.add_relay(relay).await?;
+/// Reads the profile passed with `--profile <name>`
+fn profile_from_args() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(profile) = arg.strip_prefix("--profile=") {
+            return Some(profile.to_string());
+        }
     }
-    client.connect().await;
 
-    let since = chrono::Utc::now() - chrono::Duration::days(1);
-    let timestamp = since.timestamp();
-    let since = Timestamp::from_secs(timestamp as u64);
-    // Here subscribe to get orders
-    let orders_sub_id = SubscriptionId::new("orders-sub-id");
+    None
+}
+
+/// Runs the app with the chosen profile, rebuilding everything when the user switches profiles
+async fn run(terminal: &mut DefaultTerminal) -> Result<()> {
+    let mut profile = match profile_from_args() {
+        Some(profile) if !is_valid_profile_name(&profile) => {
+            return Err(format!("Invalid profile name: {}", profile).into())
+        }
+        Some(profile) => profile,
+        None if list_profiles().is_empty() => DEFAULT_PROFILE.to_string(),
+        None => match ProfileWidget::new(None).run(terminal).await? {
+            Some(profile) => profile,
+            None => return Ok(()),
+        },
+    };
+
+    // Last profile we unlocked, the picker goes back to it
+    let mut unlocked = None;
+    loop {
+        set_profile(&profile);
+        let settings_path = get_settings_path();
+        let settings_file_path = PathBuf::from(settings_path);
+
+        // Create config global var
+        init_global_settings(Settings::new(settings_file_path)?);
+        let author = PublicKey::from_str(Settings::get().mostro_pubkey.as_str())?;
+        // Unlock the keystore before connecting to any relay
+        let (identity, keystore) = match SetupWidget::new().run(terminal).await? {
+            SetupOutcome::Unlocked(identity, keystore) => (identity, keystore),
+            SetupOutcome::Back => {
+                profile = match ProfileWidget::new(unlocked.clone()).run(terminal).await? {
+                    Some(profile) => profile,
+                    None => return Ok(()),
+                };
+                continue;
+            }
+            SetupOutcome::Quit => return Ok(()),
+        };
+        unlocked = Some(profile.clone());
+        let db = connect().await?;
+        let app = App::new(author, identity, keystore, db).await?;
+
+        let client = Client::new(&app.my_keys);
+        let relays = Settings::get().relays.clone();
+        for relay in relays {
+            client.add_relay(relay).await?;
+        }
+        client.connect().await;
+
+        let since = chrono::Utc::now() - chrono::Duration::days(1);
+        let timestamp = since.timestamp();
+        let since = Timestamp::from_secs(timestamp as u64);
+        // Here subscribe to get orders
+        let orders_sub_id = SubscriptionId::new("orders-sub-id");
+        let filter = Filter::new()
+            .author(author)
+            .kind(ParameterizedReplaceable(NOSTR_REPLACEABLE_EVENT_KIND))
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::Y), vec!["mostro"])
+            .custom_tag(SingleLetterTag::lowercase(Alphabet::Z), vec!["order"])
+            .since(since);
+        client
+            .subscribe_with_id(orders_sub_id, vec![filter], None)
+            .await?;
+
+        if app.admin_keys.is_some() {
+            subscribe_disputes(&client, author).await?;
+        }
+
+        // Here subscribe to get messages
+        subscribe_messages(&client, app.messages.public_keys()).await?;
+        let switch_profile = app.run(terminal, client.clone()).await?;
+
+        // Disconnecting stops the notification handlers of both widgets
+        client.shutdown().await?;
+        if !switch_profile {
+            return Ok(());
+        }
+        profile = match ProfileWidget::new(Some(profile)).run(terminal).await? {
+            Some(profile) => profile,
+            None => return Ok(()),
+        };
+    }
+}
+
+/// Keys we receive messages on, our identity, the keys of every trade we started
+/// and the shared keys of the chats with our counterparties
+///
+/// In admin mode we also listen on the solver keys.
+async fn listening_keys(
+    identity: &Identity,
+    pool: &SqlitePool,
+    chats: &ConversationKeys,
+) -> Result<Vec<Keys>> {
+    let mut keys = vec![identity.identity_keys()?];
+    for trade_key in TradeKey::all(pool).await? {
+        let trade_keys = identity.trade_keys(trade_key.trade_index as u32)?;
+        let order = match &trade_key.order_id {
+            Some(order_id) => db::Order::by_id(pool, order_id).await?,
+            None => None,
+        };
+        let peer = order
+            .and_then(|order| counterparty(&order, Some(&trade_key)))
+            .and_then(|peer| PublicKey::from_str(&peer).ok());
+        if let Some(peer) = peer {
+            keys.push(chats.shared_keys(&trade_keys, &peer)?);
+        }
+        keys.push(trade_keys);
+    }
+    if let Some(admin_keys) = admin_keys(identity) {
+        keys.push(admin_keys);
+    }
+
+    Ok(keys)
+}
+
+/// Solver keys from the keystore, only when admin mode is on
+fn admin_keys(identity: &Identity) -> Option<Keys> {
+    if !Settings::get().admin_mode {
+        return None;
+    }
+
+    identity
+        .admin_key()
+        .map(|secret_key| Keys::new(secret_key.clone()))
+}
+
+/// Subscribes to the disputes published by Mostro, only solvers need them
+async fn subscribe_disputes(client: &Client, mostro_pubkey: PublicKey) -> Result<()> {
     let filter = Filter::new()
-        .author(author)
+        .author(mostro_pubkey)
         .kind(ParameterizedReplaceable(NOSTR_REPLACEABLE_EVENT_KIND))
         .custom_tag(SingleLetterTag::lowercase(Alphabet::Y), vec!["mostro"])
-        .custom_tag(SingleLetterTag::lowercase(Alphabet::Z), vec!["order"])
-        .since(since);
+        .custom_tag(SingleLetterTag::lowercase(Alphabet::Z), vec!["dispute"]);
     client
-        .subscribe_with_id(orders_sub_id, vec![filter], None)
+        .subscribe_with_id(SubscriptionId::new(DISPUTES_SUB_ID), vec![filter], None)
         .await?;
 
-    // Here subscribe to get messages
+    Ok(())
+}
+
+/// Subscribes to the messages sent to any of our public keys
+///
+/// Calling it again replaces the previous subscription, so it can be used
+/// every time we start listening on a new trade key.
+async fn subscribe_messages(client: &Client, pubkeys: Vec<PublicKey>) -> Result<()> {
+    let since = chrono::Utc::now() - chrono::Duration::days(1);
+    let since = Timestamp::from_secs(since.timestamp() as u64);
     let messages_sub_id = SubscriptionId::new("messages-sub-id");
     let filter = Filter::new()
-        .pubkey(app.my_keys.public_key())
+        .pubkeys(pubkeys)
         .kinds([Kind::GiftWrap, Kind::PrivateDirectMessage])
         .since(since);
     client
         .subscribe_with_id(messages_sub_id, vec![filter], None)
         .await?;
-    let app_result = app.run(terminal, client).await;
-    ratatui::restore();
 
-    app_result
+    Ok(())
+}
+
+/// Message the messages widget got for the app
+#[derive(Debug)]
+enum Received {
+    Mostro(Box<MostroMessage>),
+    Chat(ChatMessage),
+}
+
+/// Message sent by Mostro and the key it was sent to
+#[derive(Debug)]
+struct MostroMessage {
+    receiver: PublicKey,
+    message: Message,
+    created_at: i64,
 }
 
 #[derive(Debug)]
 struct App {
+    identity: Identity,
+    keystore: Keystore,
+    identity_state: IdentityState,
     my_keys: Keys,
+    pool: SqlitePool,
     mostro_pubkey: PublicKey,
     should_quit: bool,
+    switch_profile: bool,
+    /// Trading mode of the next trade, starts with the one in the settings
+    trading_mode: TradingMode,
     show_order: bool,
+    order_form: OrderFormState,
+    /// Last thing that happened that the user should know about
+    notice: Option<String>,
     selected_tab: usize,
     orders: OrderListWidget,
+    trades: TradesWidget,
+    /// Solver keys, set only in admin mode
+    admin_keys: Option<Keys>,
+    disputes: DisputeListWidget,
     messages: MostroListWidget,
     show_amount_input: bool,
     show_invoice_input: bool,
+    /// Order Mostro asked us an invoice for
+    invoice_order: Option<Order>,
+    /// Our side of the trade we add the invoice to
+    invoice_trade: Option<Trade>,
+    invoice_input: Input,
+    invoice_error: Option<String>,
+    /// Hold invoice we have to pay as seller
+    pay_invoice: Option<PayInvoice>,
+    trade_confirm: Option<TradeConfirm>,
+    rating: Option<RatingState>,
+    /// Messages we sent that Mostro didn't answer yet
+    requests: Vec<PendingRequest>,
+    /// Chat messages of all our trades
+    chat_messages: Vec<ChatMessage>,
+    chat: Option<ChatPane>,
     amount_input: Input,
 }
 
 impl App {
     const FRAMES_PER_SECOND: f32 = 60.0;
 
-    pub fn new(mostro_pubkey: PublicKey) -> Self {
+    pub async fn new(
+        mostro_pubkey: PublicKey,
+        identity: Identity,
+        keystore: Keystore,
+        pool: SqlitePool,
+    ) -> Result<Self> {
         let amount_input = Input::default();
-
-        Self {
-            my_keys: Keys::parse(MY_PRIVATE_KEY).unwrap(),
+        let my_keys = identity.identity_keys()?;
+        let admin_keys = admin_keys(&identity);
+        let messages = MostroListWidget::default();
+        messages.set_keys(listening_keys(&identity, &pool, &messages.conversation_keys).await?);
+        let stored = db::Message::all(&pool).await?;
+        messages.load(&stored);
+        let chat_messages = stored
+            .iter()
+            .filter_map(|row| ChatMessage::from_row(row, &messages.conversation_keys))
+            .collect();
+        let trades = TradesWidget::default();
+        trades.reload(&pool).await?;
+
+        Ok(Self {
+            identity,
+            keystore,
+            identity_state: IdentityState::default(),
+            my_keys,
+            pool,
             mostro_pubkey,
             should_quit: false,
+            switch_profile: false,
+            trading_mode: Settings::get().trading_mode,
             show_order: false,
+            order_form: OrderFormState::default(),
+            notice: None,
             selected_tab: 0,
             orders: OrderListWidget::default(),
-            messages: MostroListWidget::default(),
+            trades,
+            admin_keys,
+            disputes: DisputeListWidget::default(),
+            messages,
             show_amount_input: false,
             show_invoice_input: false,
+            invoice_order: None,
+            invoice_trade: None,
+            invoice_input: Input::default(),
+            invoice_error: None,
+            pay_invoice: None,
+            trade_confirm: None,
+            rating: None,
+            requests: vec![],
+            chat_messages,
+            chat: None,
             amount_input,
+        })
+    }
+
+    /// Derives the keys for a new trade and starts listening to messages sent to them
+    ///
+    /// The derivation index is saved so the keys can be found again after a restart,
+    /// https://mostro.network/protocol/key_management.html
+    async fn new_trade_keys(
+        &self,
+        client: &Client,
+        order_id: Option<Uuid>,
+        role: &str,
+        trading_mode: TradingMode,
+    ) -> Result<Keys> {
+        // Until the restore is done we don't know which indexes were used
+        // on another machine, deriving one again would link our trades
+        if !was_restored(&self.identity, &self.pool).await? {
+            return Err(
+                "Your trades are not restored yet, wait or press s in Settings to retry".into(),
+            );
+        }
+        if let Some(order_id) = order_id {
+            self.release_stale_take(order_id).await?;
         }
+        let trade_index = TradeKey::next_index(&self.pool).await?;
+        let trade_keys = self.identity.trade_keys(trade_index as u32)?;
+        TradeKey::new(
+            trade_index,
+            order_id.map(|id| id.to_string()),
+            role,
+            trading_mode,
+        )
+        .create(&self.pool)
+        .await?;
+        self.messages.add_keys(trade_keys.clone());
+        subscribe_messages(client, self.messages.public_keys()).await?;
+
+        Ok(trade_keys)
     }
 
-    // TODO: Implement https://mostro.network/protocol/key_management.html
-    //pub fn generate_new_keys(&mut self) {
-    //  self.my_keys = Keys::generate();
-    //}
+    /// Frees an order we tried to take before and Mostro never moved, so it
+    /// can be taken again
+    async fn release_stale_take(&self, order_id: Uuid) -> Result<()> {
+        let id = order_id.to_string();
+        let Some(trade_key) = TradeKey::by_order_id(&self.pool, &id).await? else {
+            return Ok(());
+        };
+        let status = db::Order::by_id(&self.pool, &id)
+            .await?
+            .and_then(|order| order.status);
+        let pending = status.is_none_or(|status| status == Status::Pending.to_string());
+        if trade_key.role != "taker" || !pending {
+            return Err(format!("You are already trading the order {}", order_id).into());
+        }
 
-    pub async fn run(mut self, mut terminal: DefaultTerminal, client: Client) -> Result<()> {
+        self.release_take(order_id).await
+    }
+
+    /// Forgets an order we failed to take, it leaves My Trades and its trade
+    /// key isn't linked to it anymore
+    async fn release_take(&self, order_id: Uuid) -> Result<()> {
+        let id = order_id.to_string();
+        TradeKey::release(&self.pool, &id).await?;
+        db::Order::delete(&self.pool, &id).await?;
+        db::Transition::delete_by_order_id(&self.pool, &id).await?;
+        self.trades.reload(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Rebuilds our trades from the mnemonic in the background
+    ///
+    /// Once done we start listening on the restored trade keys.
+    fn restore_trades(&self, client: Client) {
+        let identity = self.identity.clone();
+        let pool = self.pool.clone();
+        let mostro_pubkey = self.mostro_pubkey;
+        let messages = self.messages.clone();
+        let trades = self.trades.clone();
+        trades.set_restore_state(RestoreState::Restoring);
+
+        tokio::spawn(async move {
+            let result = async {
+                let count = restore_session(&client, &identity, &pool, mostro_pubkey).await?;
+                messages
+                    .set_keys(listening_keys(&identity, &pool, &messages.conversation_keys).await?);
+                subscribe_messages(&client, messages.public_keys()).await?;
+                trades.reload(&pool).await?;
+                Ok::<usize, Box<dyn std::error::Error>>(count)
+            }
+            .await
+            .map_err(|e| e.to_string());
+            match result {
+                Ok(count) => trades.set_restore_state(RestoreState::Restored(count)),
+                Err(e) => trades.set_restore_state(RestoreState::Failed(e)),
+            }
+        });
+    }
+
+    /// Applies an identity change requested from the settings tab
+    ///
+    /// The new identity is saved in the keystore before being used. Our trade
+    /// keys are derived from the mnemonic, so it can't be replaced while a
+    /// trade is open and the finished trades are forgotten with their keys.
+    async fn update_identity(&mut self, command: IdentityCommand, client: &Client) -> Result<()> {
+        let mut identity = match command {
+            IdentityCommand::Generate => Identity::generate()?,
+            IdentityCommand::Import(value) if value.split_whitespace().count() == 1 => {
+                let secret_key = SecretKey::parse(&value)?;
+                self.identity.clone().with_identity_key(secret_key)
+            }
+            IdentityCommand::Import(value) => Identity::from_mnemonic(&value)?,
+            IdentityCommand::AdminKey(value) => {
+                let secret_key = SecretKey::parse(&value)?;
+                self.identity.clone().with_admin_key(secret_key)
+            }
+        };
+        // The solver key doesn't depend on the mnemonic
+        if let (None, Some(admin_key)) = (identity.admin_key(), self.identity.admin_key()) {
+            identity = identity.with_admin_key(admin_key.clone());
+        }
+        let new_mnemonic = identity.mnemonic() != self.identity.mnemonic();
+        if new_mnemonic {
+            let open = self.open_trades().await?;
+            if open > 0 {
+                return Err(format!(
+                    "{} trades are still open, finish them before replacing the mnemonic",
+                    open
+                )
+                .into());
+            }
+        }
+        // The trades of the old mnemonic go first, if that fails the
+        // keystore still matches the database
+        if new_mnemonic {
+            TradeKey::delete_all(&self.pool).await?;
+            self.trades.reload(&self.pool).await?;
+        }
+        self.keystore.save(&identity)?;
+        self.my_keys = identity.identity_keys()?;
+        self.identity = identity;
+        let was_admin = self.admin_keys.is_some();
+        self.admin_keys = admin_keys(&self.identity);
+        if !was_admin && self.admin_keys.is_some() {
+            subscribe_disputes(client, self.mostro_pubkey).await?;
+            self.disputes.run(client.clone());
+        }
+
+        self.listen(client).await
+    }
+
+    /// Number of our trades that didn't finish yet
+    async fn open_trades(&self) -> Result<usize> {
+        let mut open = 0;
+        for trade_key in TradeKey::all(&self.pool).await? {
+            let Some(order_id) = &trade_key.order_id else {
+                continue;
+            };
+            let status = db::Order::by_id(&self.pool, order_id)
+                .await?
+                .and_then(|order| order.status)
+                .and_then(|status| Status::from_str(&status).ok());
+            if status.is_some_and(|status| !trade::is_finished(status)) {
+                open += 1;
+            }
+        }
+
+        Ok(open)
+    }
+
+    /// Listens again on every key of ours, after a trade key or a chat was added
+    async fn listen(&mut self, client: &Client) -> Result<()> {
+        let chats = &self.messages.conversation_keys;
+        self.messages
+            .set_keys(listening_keys(&self.identity, &self.pool, chats).await?);
+        subscribe_messages(client, self.messages.public_keys()).await
+    }
+
+    /// Runs the app until the user quits
+    ///
+    /// Returns `true` if the user asked to switch to another profile
+    pub async fn run(mut self, terminal: &mut DefaultTerminal, client: Client) -> Result<bool> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
         self.orders.run(client.clone());
-        self.messages.run(client.clone(), self.my_keys.clone());
+        self.messages.run(client.clone(), tx, self.pool.clone());
+        if self.admin_keys.is_some() {
+            self.disputes.run(client.clone());
+        }
+        // This mnemonic was never restored here, it could be a new machine.
+        // New trades wait for it so they don't reuse a trade key
+        if !was_restored(&self.identity, &self.pool).await? {
+            self.restore_trades(client.clone());
+        }
 
         let period = Duration::from_secs_f32(1.0 / Self::FRAMES_PER_SECOND);
         let mut interval = tokio::time::interval(period);
         let mut events = EventStream::new();
 
-        while !self.should_quit {
+        while !self.should_quit && !self.switch_profile {
             tokio::select! {
-                _ = interval.tick() => { terminal.draw(|frame| self.draw(frame))?; },
+                _ = interval.tick() => {
+                    self.expire_requests().await;
+                    terminal.draw(|frame| self.draw(frame))?;
+                },
                 Some(Ok(event)) = events.next() => self.handle_event(&event, client.clone()).await,
+                Some(received) = rx.recv() => match received {
+                    Received::Mostro(message) => self.handle_mostro_message(*message, &client).await,
+                    Received::Chat(message) => self.add_chat_message(message),
+                },
             }
         }
-        Ok(())
+        Ok(self.switch_profile)
+    }
+
+    fn tab_titles(&self) -> Vec<&'static str> {
+        let mut titles = vec!["Orders", "My Trades", "Messages", "Settings"];
+        if self.admin_keys.is_some() {
+            titles.push("Disputes");
+        }
+        titles
     }
 
     fn draw(&mut self, frame: &mut Frame) {
@@ -157,14 +591,23 @@ impl App {
         let [tabs_area, body_area] = vertical.areas(frame.area());
 
         // Defining tabs labels
-        let tab_titles = ["Orders", "My Trades", "Messages", "Settings"]
+        let tab_titles = self
+            .tab_titles()
             .iter()
             .map(|t| Line::from(*t).bold())
             .collect::<Vec<Line>>();
         let color = Color::from_str("#304F00").unwrap();
+        let profile = get_profile();
+        let tabs_title = if profile == DEFAULT_PROFILE {
+            " Mostro ".to_string()
+        } else {
+            format!(" Mostro · {} ", profile)
+        };
+
+        let notice = Line::from(self.notice.clone().unwrap_or_default()).right_aligned();
 
         let tabs = Tabs::new(tab_titles)
-            .block(Block::bordered().title(" Mostro "))
+            .block(Block::bordered().title(tabs_title).title_bottom(notice))
             .bg(color)
             .select(self.selected_tab)
             .highlight_style(Style::new().fg(BLUE.c400));
@@ -173,31 +616,51 @@ impl App {
 
         match self.selected_tab {
             0 => self.render_orders_tab(frame, body_area),
-            1 => self.render_text_tab(frame, body_area, "My Trades"),
+            1 => self.render_trades_tab(frame, body_area),
             2 => self.render_messages_tab(frame, body_area),
             3 => self.render_settings_tab(frame, body_area),
+            4 => frame.render_widget(&self.disputes, body_area),
             _ => {}
         }
 
         if self.show_invoice_input {
-            let popup_area = popup_area(frame.area(), 50, 20);
-            let block = Block::bordered().title("Invoice input").bg(Color::Black);
+            let popup_area = popup_area(frame.area(), 50, 40);
+            let mut block = Block::bordered()
+                .title("Invoice input")
+                .bg(Color::Black)
+                .title_bottom("ESC to close, ENTER to send invoice");
+            if let Some(error) = &self.invoice_error {
+                block = block.title_bottom(Line::from(error.as_str()).red().right_aligned());
+            }
+            let (amount, order_id) = match &self.invoice_order {
+                Some(order) => (
+                    order.amount,
+                    order.id.map(|id| id.to_string()).unwrap_or_default(),
+                ),
+                None => (0, String::new()),
+            };
             let lines = vec![
-                Line::raw("🧌 You took this selling order, please use a fiat payment processor that allows you to send the money immediately and in which there is no risk of freezing funds."),
+                Line::raw(invoice_intro(self.invoice_trade.as_ref())),
                 Line::raw("If, for any reason, your payment processor puts the payment on pause and the funds do not arrive in less than 22 hours, the sats will return to the seller, putting the buyer at risk and I cannot force the seller to send the sats again."),
-                Line::raw("If you agree with the above, enter a lightning invoice."),
+                Line::raw(format!("If you agree with the above, enter a lightning invoice of {} sats for the order {}.", amount, order_id)),
             ];
             let paragraph = Paragraph::new(lines).block(block).wrap(Wrap { trim: true });
-            let input_paragraph = Paragraph::new(vec![Line::from(self.amount_input.value())])
+            let input_paragraph = Paragraph::new(vec![Line::from(self.invoice_input.value())])
                 .block(Block::default().borders(ratatui::widgets::Borders::ALL))
                 .wrap(Wrap { trim: true });
             frame.render_widget(Clear, popup_area);
             frame.render_widget(paragraph, popup_area);
 
             // Render input
+            let input_height = 5.min(popup_area.height.saturating_sub(2));
             frame.render_widget(
                 input_paragraph,
-                Rect::new(popup_area.x, popup_area.y + 4, popup_area.width, 3),
+                Rect::new(
+                    popup_area.x + 1,
+                    popup_area.y + popup_area.height - input_height - 1,
+                    popup_area.width.saturating_sub(2),
+                    input_height,
+                ),
             );
         }
 
@@ -208,7 +671,7 @@ impl App {
                 .title("Amount input".to_string())
                 .bg(color)
                 .title_style(Style::new().fg(Color::White))
-                .title_bottom(format!("ESC to close, ENTER to send fiat amount"));
+                .title_bottom("ESC to close, ENTER to send fiat amount");
             let selected = self.orders.state.read().unwrap().table_state.selected();
             let state = self.orders.state.read().unwrap();
             let order = match selected {
@@ -241,6 +704,26 @@ impl App {
             );
         }
 
+        if self.order_form.visible {
+            frame.render_widget(OrderFormWidget::new(&self.order_form), frame.area());
+        }
+
+        if let Some(chat) = &self.chat {
+            frame.render_widget(ChatWidget::new(chat, &self.chat_messages), frame.area());
+        }
+
+        if let Some(trade_confirm) = &self.trade_confirm {
+            frame.render_widget(trade_confirm, frame.area());
+        }
+
+        if let Some(pay_invoice) = &self.pay_invoice {
+            frame.render_widget(PayInvoiceWidget::new(pay_invoice), frame.area());
+        }
+
+        if let Some(rating) = &self.rating {
+            frame.render_widget(rating, frame.area());
+        }
+
         if self.show_order {
             let popup_area = popup_area(frame.area(), 50, 60);
             let selected = self.orders.state.read().unwrap().table_state.selected();
@@ -264,7 +747,10 @@ impl App {
                 .title("Order details".to_string())
                 .bg(color)
                 .title_style(Style::new().fg(Color::White))
-                .title_bottom(format!("ESC to close, ENTER to {}", action));
+                .title_bottom(format!(
+                    "ESC to close, m to change trading mode, ENTER to {}",
+                    action
+                ));
             let sats_amount = order.sats_amount();
             let premium = match order.premium.cmp(&0) {
                 Ordering::Equal => "No premium or discount".to_string(),
@@ -285,6 +771,24 @@ impl App {
                 Line::raw(format!("Id: {}", order.id.unwrap())),
                 Line::raw(""),
                 Line::raw(format!("Created at: {}", created_at)),
+                match order.expires_at {
+                    Some(expires_at) if trade::is_expiring(expires_at) => {
+                        Line::from(format!("Expires in: {}", countdown(expires_at)))
+                            .red()
+                            .bold()
+                    }
+                    Some(expires_at) => Line::raw(format!("Expires in: {}", countdown(expires_at))),
+                    None => Line::raw(""),
+                },
+                Line::raw(""),
+                Line::raw(match self.trading_mode {
+                    TradingMode::Reputation => {
+                        "Trading mode: reputation, this trade will be linked to your identity."
+                    }
+                    TradingMode::Privacy => {
+                        "Trading mode: privacy, this trade won't be linked to your identity."
+                    }
+                }),
             ];
             let paragraph = Paragraph::new(lines)
                 .block(block)
@@ -304,17 +808,773 @@ impl App {
         frame.render_widget(&self.messages, area);
     }
 
-    fn render_text_tab(&self, frame: &mut Frame, area: Rect, text: &str) {
-        let text_line = Line::from(text).centered();
-        frame.render_widget(text_line, area);
+    fn render_trades_tab(&self, frame: &mut Frame, area: Rect) {
+        frame.render_widget(&self.trades, area);
     }
 
     fn render_settings_tab(&self, frame: &mut Frame, area: Rect) {
         let settings_widget =
-            SettingsWidget::new(self.mostro_pubkey, self.my_keys.secret_key().clone());
+            SettingsWidget::new(self.mostro_pubkey, &self.identity, &self.identity_state);
         frame.render_widget(settings_widget, area);
     }
 
+    /// Keys used in the trade of an order and how the seal must be signed
+    async fn order_keys(&self, order_id: Uuid) -> Result<(Keys, TradingMode)> {
+        let trade_key = TradeKey::by_order_id(&self.pool, &order_id.to_string())
+            .await?
+            .ok_or(format!("No trade key found for order {}", order_id))?;
+        let keys = self.identity.trade_keys(trade_key.trade_index as u32)?;
+        let trading_mode = TradingMode::from_str(&trade_key.trading_mode)?;
+
+        Ok((keys, trading_mode))
+    }
+
+    /// Our side of the trade of `order_id`, if it is one of ours
+    async fn trade(&self, order_id: Uuid) -> Result<Option<Trade>> {
+        let id = order_id.to_string();
+        let (Some(order), Some(trade_key)) = (
+            db::Order::by_id(&self.pool, &id).await?,
+            TradeKey::by_order_id(&self.pool, &id).await?,
+        ) else {
+            return Ok(None);
+        };
+
+        Ok(Trade::new(&order, &trade_key).ok())
+    }
+
+    /// Sends a message about one of our orders to Mostro, signed with its trade keys
+    async fn send_order_message(
+        &mut self,
+        order_id: Uuid,
+        action: Action,
+        content: Option<Content>,
+        client: &Client,
+    ) -> Result<()> {
+        let (trade_keys, trading_mode) = self.order_keys(order_id).await?;
+        let request = PendingRequest::new(Some(order_id), action.clone());
+        let request_id = Some(request.request_id);
+        let message = match action {
+            Action::Dispute => Message::new_dispute(request_id, Some(order_id), action, content),
+            _ => Message::new_order(request_id, Some(order_id), action, content),
+        };
+        let message = message
+            .as_json()
+            .map_err(|e| format!("Error serializing message to JSON: {}", e))?;
+        let seal_keys = trading_mode.seal_keys(&self.my_keys, &trade_keys);
+        let event = gift_wrap(seal_keys, &trade_keys, self.mostro_pubkey, message, None, 0)
+            .map_err(|e| format!("Error creating event: {}", e))?;
+
+        let msg = ClientMessage::event(event);
+        client.send_msg_to(Settings::get().relays, msg).await?;
+        self.requests.push(request);
+
+        Ok(())
+    }
+
+    /// Sends a solver command to Mostro, signed with the admin keys
+    async fn send_admin_message(
+        &mut self,
+        id: Uuid,
+        action: Action,
+        client: &Client,
+    ) -> Result<()> {
+        let admin_keys = self.admin_keys.clone().ok_or("Admin mode is off")?;
+        let request = PendingRequest::new(Some(id), action.clone());
+        let request_id = Some(request.request_id);
+        let message = match action {
+            Action::AdminTakeDispute => Message::new_dispute(request_id, Some(id), action, None),
+            _ => Message::new_order(request_id, Some(id), action, None),
+        };
+        let message = message
+            .as_json()
+            .map_err(|e| format!("Error serializing message to JSON: {}", e))?;
+        let event = gift_wrap(
+            &admin_keys,
+            &admin_keys,
+            self.mostro_pubkey,
+            message,
+            None,
+            0,
+        )
+        .map_err(|e| format!("Error creating event: {}", e))?;
+
+        let msg = ClientMessage::event(event);
+        client.send_msg_to(Settings::get().relays, msg).await?;
+        self.requests.push(request);
+
+        Ok(())
+    }
+
+    /// Sends the invoice typed in the invoice popup to Mostro
+    async fn send_invoice(&mut self, client: &Client) -> Result<()> {
+        let invoice = normalize_invoice(self.invoice_input.value());
+        if !is_valid_bolt11(&invoice) {
+            return Err("This is not a valid lightning invoice".into());
+        }
+        let order_id = self
+            .invoice_order
+            .as_ref()
+            .and_then(|o| o.id)
+            .ok_or("Order ID is missing")?;
+        let content = Content::PaymentRequest(None, invoice, None);
+        self.send_order_message(order_id, Action::AddInvoice, Some(content), client)
+            .await
+    }
+
+    /// Sends a new order to Mostro, it will be saved once Mostro confirms it
+    async fn publish_order(
+        &mut self,
+        order: Order,
+        trading_mode: TradingMode,
+        client: &Client,
+    ) -> Result<()> {
+        let request = PendingRequest::new(None, Action::NewOrder);
+        let message = Message::new_order(
+            Some(request.request_id),
+            None,
+            Action::NewOrder,
+            Some(Content::Order(order)),
+        )
+        .as_json()
+        .map_err(|e| format!("Error serializing message to JSON: {}", e))?;
+        let trade_keys = self
+            .new_trade_keys(client, None, "maker", trading_mode)
+            .await?;
+        let seal_keys = trading_mode.seal_keys(&self.my_keys, &trade_keys);
+        let event = gift_wrap(seal_keys, &trade_keys, self.mostro_pubkey, message, None, 0)
+            .map_err(|e| format!("Error creating event: {}", e))?;
+
+        let msg = ClientMessage::event(event);
+        client.send_msg_to(Settings::get().relays, msg).await?;
+        self.requests.push(request);
+
+        Ok(())
+    }
+
+    /// Acts on the messages Mostro sends to our keys
+    async fn handle_mostro_message(&mut self, message: MostroMessage, client: &Client) {
+        if let Err(e) = self.apply_mostro_message(&message, client).await {
+            let action = &message.message.get_inner_message_kind().action;
+            self.notice = Some(format!("Error handling {} message: {}", action, e));
+        }
+    }
+
+    /// Updates the trade, the pending requests and the screen with a message
+    ///
+    /// Each part runs whatever the state machine says about the message, so
+    /// messages replayed by the relays on start still bring back the popups
+    /// of the steps we didn't finish.
+    async fn apply_mostro_message(
+        &mut self,
+        message: &MostroMessage,
+        client: &Client,
+    ) -> Result<()> {
+        let inner = message.message.get_inner_message_kind();
+        let to_admin = self.admin_keys.as_ref().map(|k| k.public_key()) == Some(message.receiver);
+        let latest = match (inner.id, to_admin) {
+            (Some(order_id), false) => self.update_trade(order_id, message, client).await?,
+            _ => true,
+        };
+        if let Some(order_id) = self.match_request(inner, latest) {
+            self.release_take(order_id).await?;
+        }
+        self.show_message(message, to_admin, latest).await
+    }
+
+    /// Runs the state machine of one of our trades with a message from Mostro
+    ///
+    /// Returns false if Mostro already sent us a newer message about the
+    /// trade, what this one asks for is out of date then.
+    async fn update_trade(
+        &mut self,
+        order_id: Uuid,
+        message: &MostroMessage,
+        client: &Client,
+    ) -> Result<bool> {
+        let inner = message.message.get_inner_message_kind();
+        match self
+            .advance_trade(order_id, &inner.action, message.created_at)
+            .await?
+        {
+            Some(Applied::Flagged(status)) => {
+                self.notice = Some(format!(
+                    "Unexpected {} for order {} while it is {}, ignored",
+                    inner.action, order_id, status
+                ));
+            }
+            Some(Applied::Moved { from, to }) => {
+                let step_started = from != to && trade::has_deadline(to);
+                let asked_at = step_started.then_some(message.created_at);
+                self.update_trade_details(order_id, &inner.content, asked_at, client)
+                    .await?
+            }
+            Some(Applied::Seen) | None => {}
+        }
+        let transitions = db::Transition::by_order_id(&self.pool, &order_id.to_string()).await?;
+
+        Ok(transitions
+            .last()
+            .is_none_or(|t| t.created_at <= message.created_at))
+    }
+
+    /// Matches an answer from Mostro with the request we sent and shows its error
+    ///
+    /// Errors older than the last message of their trade were already fixed.
+    /// Returns the order of a take Mostro refused, we aren't trading it.
+    fn match_request(&mut self, inner: &MessageKind, latest: bool) -> Option<Uuid> {
+        let error = error_text(&inner.action, &inner.content);
+        // Show the replies to the command we just sent in its popup
+        if let Some(trade_confirm) = &mut self.trade_confirm {
+            if trade_confirm.sent && inner.id == Some(trade_confirm.order_id) {
+                let reply = error.clone().unwrap_or_else(|| reply_text(&inner.action));
+                trade_confirm.replies.push(reply);
+            }
+        }
+        let request = self.take_request(inner, error.is_some());
+        let order_id = inner.id.or(request.as_ref().and_then(|r| r.order_id));
+        let failed_take = request
+            .as_ref()
+            .filter(|r| is_take(&r.action) && error.is_some())
+            .and(order_id);
+        match (error, order_id) {
+            (Some(error), order_id) if latest => {
+                let error = match &request {
+                    Some(request) => format!("{} failed: {}", request.action, error),
+                    None => error,
+                };
+                if let Some(order_id) = order_id {
+                    self.trades.set_error(order_id, error.clone());
+                }
+                self.notice = Some(error);
+            }
+            (None, Some(order_id)) if request.is_some() => self.trades.clear_error(order_id),
+            _ => {}
+        }
+
+        failed_take
+    }
+
+    /// Shows what a message from Mostro asks us to do
+    async fn show_message(
+        &mut self,
+        message: &MostroMessage,
+        to_admin: bool,
+        latest: bool,
+    ) -> Result<()> {
+        let inner = message.message.get_inner_message_kind();
+        match (&inner.action, &inner.content) {
+            (Action::NewOrder, Some(Content::Order(order))) => {
+                self.confirm_new_order(message.receiver, order).await
+            }
+            (Action::PayInvoice, Some(Content::PaymentRequest(Some(order), invoice, _))) => {
+                match inner.id.or(order.id) {
+                    Some(order_id) => {
+                        let pay_invoice = PayInvoice::new(
+                            order_id,
+                            invoice.clone(),
+                            order.amount,
+                            message.created_at,
+                            order.expires_at,
+                        );
+                        // Old messages are fetched again on start, skip the expired ones
+                        if latest && pay_invoice.deadline > chrono::Utc::now().timestamp() {
+                            self.pay_invoice = Some(pay_invoice);
+                        }
+                        Ok(())
+                    }
+                    None => Err("Order ID is missing".into()),
+                }
+            }
+            (
+                Action::HoldInvoicePaymentAccepted
+                | Action::BuyerTookOrder
+                | Action::WaitingBuyerInvoice
+                | Action::HoldInvoicePaymentCanceled,
+                _,
+            ) => {
+                self.close_pay_invoice(inner.id);
+                Ok(())
+            }
+            (Action::CooperativeCancelInitiatedByPeer, _) => match inner.id {
+                Some(order_id) => {
+                    if latest && self.trade_confirm.is_none() {
+                        self.trade_confirm =
+                            Some(TradeConfirm::cancel(order_id, CancelMode::PeerAsked));
+                    } else {
+                        self.notice = Some(format!("{}: {}", order_id, reply_text(&inner.action)));
+                    }
+                    Ok(())
+                }
+                None => Err("Order ID is missing".into()),
+            },
+            (Action::CooperativeCancelInitiatedByYou, _) => {
+                self.notice = Some(reply_text(&inner.action));
+                Ok(())
+            }
+            (Action::CooperativeCancelAccepted | Action::Canceled, _) => {
+                self.close_pay_invoice(inner.id);
+                match inner.id {
+                    Some(order_id) => {
+                        self.notice = Some(format!("{}: {}", order_id, reply_text(&inner.action)));
+                        Ok(())
+                    }
+                    None => Err("Order ID is missing".into()),
+                }
+            }
+            (
+                Action::FiatSentOk
+                | Action::HoldInvoicePaymentSettled
+                | Action::Released
+                | Action::PurchaseCompleted,
+                _,
+            )
+            | (Action::AdminSettled | Action::AdminCanceled, _) => match inner.id {
+                Some(order_id) => {
+                    self.notice = Some(format!("{}: {}", order_id, reply_text(&inner.action)));
+                    Ok(())
+                }
+                None => Err("Order ID is missing".into()),
+            },
+            (Action::Rate, _) => match inner.id {
+                Some(order_id) => {
+                    // Mostro asks again on every start, only rate each trade once
+                    if db::Rating::by_order_id(&self.pool, &order_id.to_string())
+                        .await?
+                        .is_none()
+                    {
+                        self.rating = Some(RatingState::new(order_id));
+                    }
+                    Ok(())
+                }
+                None => Err("Order ID is missing".into()),
+            },
+            (Action::DisputeInitiatedByYou | Action::DisputeInitiatedByPeer, content) => {
+                let token = match content {
+                    Some(Content::Dispute(_, token)) => *token,
+                    _ => None,
+                };
+                match inner.id {
+                    Some(order_id) => {
+                        self.notice = Some(format!("{}: {}", order_id, reply_text(&inner.action)));
+                        self.save_dispute_token(order_id, token).await
+                    }
+                    None => Err("Order ID is missing".into()),
+                }
+            }
+            (Action::AdminTookDispute, Some(Content::Order(order))) if to_admin => match inner.id {
+                Some(dispute_id) => {
+                    self.disputes.add_order(dispute_id, order.clone());
+                    self.notice = Some(format!("You took the dispute {}", dispute_id));
+                    Ok(())
+                }
+                None => Err("Dispute ID is missing".into()),
+            },
+            (Action::AdminTookDispute, _) => {
+                self.notice = Some(reply_text(&inner.action));
+                Ok(())
+            }
+            (Action::RateReceived, _) => {
+                self.notice = Some("Mostro received your rating".to_string());
+                Ok(())
+            }
+            // Once answered Mostro moves the trade on, the popup is only for the last message
+            (Action::AddInvoice, Some(Content::Order(order))) if latest => {
+                self.invoice_trade = match inner.id.or(order.id) {
+                    Some(order_id) => self.trade(order_id).await?,
+                    None => None,
+                };
+                self.invoice_order = Some(order.clone());
+                self.invoice_input.reset();
+                self.invoice_error = None;
+                self.show_invoice_input = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the request Mostro answered from the pending ones
+    ///
+    /// Answers are matched by request id, Mostro versions that don't send it
+    /// back are matched by order when the message is an error or a reply to
+    /// the request, other messages about the order leave it pending.
+    fn take_request(&mut self, inner: &MessageKind, is_error: bool) -> Option<PendingRequest> {
+        let position = match inner.request_id {
+            Some(request_id) => self
+                .requests
+                .iter()
+                .position(|r| r.request_id == request_id),
+            None => self.requests.iter().position(|r| {
+                inner.id.is_some()
+                    && r.order_id == inner.id
+                    && (is_error || r.answered_by(&inner.action))
+            }),
+        };
+
+        position.map(|i| self.requests.remove(i))
+    }
+
+    /// Shows the requests Mostro didn't answer in time, takes without answer
+    /// are released so the order can be taken again
+    async fn expire_requests(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        let (expired, pending): (Vec<_>, Vec<_>) =
+            self.requests.drain(..).partition(|r| r.timed_out(now));
+        self.requests = pending;
+        for request in expired {
+            let mut error = format!("{} timed out, Mostro didn't answer", request.action);
+            if let (true, Some(order_id)) = (is_take(&request.action), request.order_id) {
+                if let Err(e) = self.release_take(order_id).await {
+                    error = format!("{}, error releasing the order: {}", error, e);
+                }
+            }
+            if let Some(order_id) = request.order_id {
+                self.trades.set_error(order_id, error.clone());
+                if let Some(trade_confirm) = &mut self.trade_confirm {
+                    if trade_confirm.sent && trade_confirm.order_id == order_id {
+                        trade_confirm.replies.push(error.clone());
+                    }
+                }
+            }
+            self.notice = Some(error);
+        }
+    }
+
+    /// Closes the hold invoice popup once Mostro got the payment or the order is gone
+    fn close_pay_invoice(&mut self, order_id: Option<Uuid>) {
+        if self.pay_invoice.as_ref().map(|p| p.order_id) == order_id {
+            self.pay_invoice = None;
+        }
+    }
+
+    /// Sends the rating of the counterparty and remembers we rated the trade
+    async fn send_rating(&mut self, order_id: Uuid, rating: u8, client: &Client) -> Result<()> {
+        let content = Content::RatingUser(rating);
+        self.send_order_message(order_id, Action::RateUser, Some(content), client)
+            .await?;
+        db::Rating::new(order_id.to_string(), rating)
+            .create(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Saves the order Mostro published for us and links it to its trade key
+    async fn confirm_new_order(&mut self, receiver: PublicKey, order: &Order) -> Result<()> {
+        let order_id = order.id.ok_or("Order ID is missing")?.to_string();
+        // Messages are fetched again on every start, the order may be further along
+        if db::Order::by_id(&self.pool, &order_id).await?.is_some() {
+            return Ok(());
+        }
+        for trade_key in TradeKey::all(&self.pool).await? {
+            let keys = self.identity.trade_keys(trade_key.trade_index as u32)?;
+            if trade_key.order_id.is_none() && keys.public_key() == receiver {
+                TradeKey::set_order_id(&self.pool, trade_key.trade_index, &order_id).await?;
+                break;
+            }
+        }
+        self.save_trade(order).await?;
+        self.notice = Some(format!("Order {} published", order_id));
+
+        Ok(())
+    }
+
+    /// Moves one of our trades with a message from Mostro
+    ///
+    /// Returns None if the order isn't one of our trades.
+    async fn advance_trade(
+        &mut self,
+        order_id: Uuid,
+        action: &Action,
+        created_at: i64,
+    ) -> Result<Option<Applied>> {
+        let id = order_id.to_string();
+        let Some(mut order) = db::Order::by_id(&self.pool, &id).await? else {
+            return Ok(None);
+        };
+        let Some(trade_key) = TradeKey::by_order_id(&self.pool, &id).await? else {
+            return Ok(None);
+        };
+        let applied = trade::apply(&self.pool, &mut order, &trade_key, action, created_at).await?;
+        if applied != Applied::Seen {
+            self.trades.reload(&self.pool).await?;
+        }
+
+        Ok(Some(applied))
+    }
+
+    /// Saves what Mostro tells us about a trade as it goes on, like the
+    /// counterparty and the amounts of range and market price orders
+    ///
+    /// `asked_at` is set when Mostro started a step with a deadline, like
+    /// paying the hold invoice or adding an invoice. Once we know the
+    /// counterparty we listen on the shared key of its chat.
+    async fn update_trade_details(
+        &mut self,
+        order_id: Uuid,
+        content: &Option<Content>,
+        asked_at: Option<i64>,
+        client: &Client,
+    ) -> Result<()> {
+        let id = order_id.to_string();
+        let Some(mut order) = db::Order::by_id(&self.pool, &id).await? else {
+            return Ok(());
+        };
+        let before = order.clone();
+        let mut expires_at = None;
+        match content {
+            Some(Content::Order(details) | Content::PaymentRequest(Some(details), _, _)) => {
+                if details.master_buyer_pubkey.is_some() {
+                    order.master_buyer_pubkey = details.master_buyer_pubkey.clone();
+                }
+                if details.master_seller_pubkey.is_some() {
+                    order.master_seller_pubkey = details.master_seller_pubkey.clone();
+                }
+                if details.amount > 0 {
+                    order.amount = details.amount;
+                }
+                if details.fiat_amount > 0 {
+                    order.fiat_amount = details.fiat_amount;
+                }
+                expires_at = details.expires_at;
+            }
+            Some(Content::Peer(peer)) => {
+                let Some(trade_key) = TradeKey::by_order_id(&self.pool, &id).await? else {
+                    return Ok(());
+                };
+                match Role::of(&order, &trade_key) {
+                    Role::Buyer => order.master_seller_pubkey = Some(peer.pubkey.clone()),
+                    Role::Seller => order.master_buyer_pubkey = Some(peer.pubkey.clone()),
+                }
+            }
+            _ => {}
+        }
+        if let Some(asked_at) = asked_at {
+            order.expires_at = Some(trade::step_deadline(asked_at, expires_at));
+        }
+        if order != before {
+            order.save(&self.pool).await?;
+            self.trades.reload(&self.pool).await?;
+        }
+        let new_peer = order.master_buyer_pubkey != before.master_buyer_pubkey
+            || order.master_seller_pubkey != before.master_seller_pubkey;
+        if new_peer {
+            self.listen(client).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the dispute token Mostro gave us, the solver asks for it to tell
+    /// who is who
+    async fn save_dispute_token(&mut self, order_id: Uuid, token: Option<u16>) -> Result<()> {
+        let id = order_id.to_string();
+        let Some(mut order) = db::Order::by_id(&self.pool, &id).await? else {
+            return Ok(());
+        };
+        let trade_key = TradeKey::by_order_id(&self.pool, &id)
+            .await?
+            .ok_or(format!("No trade key found for order {}", order_id))?;
+        match Role::of(&order, &trade_key) {
+            Role::Buyer => order.buyer_token = token,
+            Role::Seller => order.seller_token = token,
+        }
+        order.save(&self.pool).await?;
+        self.trades.reload(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Asks for confirmation before sending a command on the selected trade
+    fn confirm_trade_action(&mut self, action: Action) {
+        let Some((order, trade_key)) = self.trades.selected() else {
+            self.notice = Some("Select one of your trades first".to_string());
+            return;
+        };
+        let Some(order_id) = order.id.as_deref().and_then(|id| Uuid::parse_str(id).ok()) else {
+            self.notice = Some("Order ID is missing".to_string());
+            return;
+        };
+        let trade = match Trade::new(&order, &trade_key) {
+            Ok(trade) => trade,
+            Err(e) => {
+                self.notice = Some(e);
+                return;
+            }
+        };
+        if let Err(e) = trade.check(&action) {
+            self.notice = Some(e);
+            return;
+        }
+        match action {
+            Action::Cancel => {
+                let cancel = if self.trades.has_cancel_request(order_id) {
+                    CancelMode::PeerAsked
+                } else if trade.status == Status::Pending {
+                    CancelMode::Pending
+                } else if matches!(
+                    trade.status,
+                    Status::WaitingPayment | Status::WaitingBuyerInvoice
+                ) {
+                    CancelMode::Unilateral
+                } else {
+                    CancelMode::Cooperative
+                };
+                self.trade_confirm = Some(TradeConfirm::cancel(order_id, cancel))
+            }
+            _ => self.trade_confirm = Some(TradeConfirm::new(order_id, action)),
+        }
+    }
+
+    /// Opens the invoice popup for the selected trade, Mostro is waiting for it
+    fn open_invoice_input(&mut self) {
+        let Some((order, trade_key)) = self.trades.selected() else {
+            self.notice = Some("Select one of your trades first".to_string());
+            return;
+        };
+        let Some(order_id) = order.id.as_deref().and_then(|id| Uuid::parse_str(id).ok()) else {
+            self.notice = Some("Order ID is missing".to_string());
+            return;
+        };
+        let trade = Trade::new(&order, &trade_key)
+            .and_then(|trade| trade.check(&Action::AddInvoice).map(|()| trade));
+        match trade {
+            Ok(trade) => self.invoice_trade = Some(trade),
+            Err(e) => {
+                self.notice = Some(e);
+                return;
+            }
+        }
+        self.invoice_order = Some(Order {
+            id: Some(order_id),
+            amount: order.amount,
+            ..Default::default()
+        });
+        self.invoice_input.reset();
+        self.invoice_error = None;
+        self.show_invoice_input = true;
+    }
+
+    /// Opens the chat with the counterparty of the selected trade
+    fn open_chat(&mut self) {
+        let Some((order, trade_key)) = self.trades.selected() else {
+            self.notice = Some("Select one of your trades first".to_string());
+            return;
+        };
+        let Some(order_id) = order.id.as_deref().and_then(|id| Uuid::parse_str(id).ok()) else {
+            self.notice = Some("Order ID is missing".to_string());
+            return;
+        };
+        let Some(peer) =
+            counterparty(&order, Some(&trade_key)).and_then(|p| PublicKey::from_str(&p).ok())
+        else {
+            self.notice = Some("Mostro didn't tell us who your counterparty is yet".to_string());
+            return;
+        };
+        let chats = &self.messages.conversation_keys;
+        let chat = self
+            .identity
+            .trade_keys(trade_key.trade_index as u32)
+            .and_then(|trade_keys| ChatPane::new(order_id, trade_keys, peer, chats));
+        match chat {
+            Ok(chat) => self.chat = Some(chat),
+            Err(e) => self.notice = Some(format!("Error deriving trade keys: {}", e)),
+        }
+    }
+
+    /// Sends a NIP-17 message to the shared key of the open chat
+    async fn send_chat_message(&mut self, text: String, client: &Client) -> Result<()> {
+        let chat = self.chat.as_ref().ok_or("No chat open")?;
+        let receiver = chat.shared_keys.public_key();
+        let event = private_message(&chat.trade_keys, receiver, text.clone())
+            .map_err(|e| format!("Error creating event: {}", e))?;
+        let row = db::Message {
+            id: event.id.to_string(),
+            rumor_id: None,
+            sender: chat.trade_keys.public_key().to_string(),
+            receiver: receiver.to_string(),
+            order_id: Some(chat.order_id.to_string()),
+            action: None,
+            content: text,
+            kind: Kind::PrivateDirectMessage.as_u16(),
+            verified: true,
+            created_at: chrono::Utc::now().timestamp(),
+            read: true,
+        };
+
+        let msg = ClientMessage::event(event);
+        client.send_msg_to(Settings::get().relays, msg).await?;
+        row.create(&self.pool).await?;
+        if let Some(dm) = DM::from_row(&row) {
+            self.messages.add(dm);
+        }
+        if let Some(message) = ChatMessage::from_row(&row, &self.messages.conversation_keys) {
+            self.add_chat_message(message);
+        }
+
+        Ok(())
+    }
+
+    /// Saves that the user saw the selected message of the Messages tab
+    async fn mark_message_read(&mut self) {
+        if let Some(id) = self.messages.read_selected() {
+            if let Err(e) = db::Message::mark_read(&self.pool, &id).await {
+                self.notice = Some(format!("Error saving message as read: {}", e));
+            }
+        }
+    }
+
+    fn add_chat_message(&mut self, message: ChatMessage) {
+        if !self.chat_messages.iter().any(|m| m.id == message.id) {
+            self.chat_messages.push(message);
+        }
+    }
+
+    /// Keeps the order we are trading in the database so it shows in My Trades
+    async fn save_trade(&self, order: &Order) -> Result<()> {
+        db::Order::from(order).create(&self.pool).await?;
+        self.trades.reload(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Sends the take message with new trade keys and adds the order to My Trades
+    ///
+    /// The order is released again if the message can't be sent.
+    async fn send_take(
+        &mut self,
+        order: &Order,
+        action: Action,
+        content: Option<Content>,
+        client: &Client,
+    ) -> Result<()> {
+        let order_id = order.id.ok_or("Order ID is missing")?;
+        let request = PendingRequest::new(Some(order_id), action.clone());
+        let message = Message::new_order(Some(request.request_id), Some(order_id), action, content)
+            .as_json()
+            .map_err(|e| format!("Error serializing message to JSON: {}", e))?;
+
+        let trade_keys = self
+            .new_trade_keys(client, Some(order_id), "taker", self.trading_mode)
+            .await?;
+        let seal_keys = self.trading_mode.seal_keys(&self.my_keys, &trade_keys);
+        let sent = async {
+            let event = gift_wrap(seal_keys, &trade_keys, self.mostro_pubkey, message, None, 0)
+                .map_err(|e| format!("Error creating event: {}", e))?;
+            let msg = ClientMessage::event(event);
+            client.send_msg_to(Settings::get().relays, msg).await?;
+            self.save_trade(order).await
+        }
+        .await;
+        if let Err(e) = sent {
+            self.release_take(order_id).await?;
+            return Err(e);
+        }
+        self.requests.push(request);
+
+        Ok(())
+    }
+
     async fn take_order(&mut self, order: Order, action: Action, client: &Client) -> Result<()> {
         if self.show_amount_input {
             match self.amount_input.value().parse::<i64>() {
@@ -325,44 +1585,21 @@ impl App {
                         self.show_amount_input = false;
                         self.show_order = false;
 
-                        // TODO: Implement https://mostro.network/protocol/key_management.html
-                        let order_id = match order.id {
-                            Some(id) => id,
-                            None => {
-                                println!("Order ID is missing");
-                                return Err("Order ID is missing".into());
-                            }
-                        };
-                        let message = Message::new_order(
-                            None,
-                            Some(order_id),
-                            action,
-                            Some(Content::Amount(value)),
-                        )
-                        .as_json()
-                        .map_err(|e| format!("Error serializing message to JSON: {}", e))?;
-
-                        println!(
-                            "Taking the order {} for {} {} with payment method {}",
-                            order.id.unwrap(),
-                            value,
-                            order.fiat_code,
-                            order.payment_method
-                        );
-
-                        let event = gift_wrap(&self.my_keys, self.mostro_pubkey, message, None, 0)
-                            .map_err(|e| format!("Error creating event: {}", e))?;
-
-                        let msg = ClientMessage::event(event);
-                        client.send_msg_to(Settings::get().relays, msg).await?;
+                        let order_id = order.id.ok_or("Order ID is missing")?;
+                        let content = Some(Content::Amount(value));
+                        self.send_take(&order, action, content, client).await?;
+                        self.notice = Some(format!(
+                            "Taking the order {} for {} {}",
+                            order_id, value, order.fiat_code
+                        ));
                     } else {
                         self.show_amount_input = false;
-                        println!("Value {} is out of the order range", value);
+                        self.notice = Some(format!("{} is out of the order range", value));
                     }
                 }
                 Err(_) => {
                     self.show_amount_input = false;
-                    println!("Invalid amount input. Please enter a valid number.");
+                    self.notice = Some("Enter the amount as a whole number".to_string());
                 }
             }
         } else if self.show_order {
@@ -370,34 +1607,16 @@ impl App {
                 self.show_amount_input = true;
                 self.show_order = false;
             } else {
-                // TODO: Implement https://mostro.network/protocol/key_management.html
-                let order_id = match order.id {
-                    Some(id) => id,
-                    None => {
-                        println!("Order ID is missing");
-                        return Err("Order ID is missing".into());
-                    }
-                };
-                let message = Message::new_order(None, Some(order_id), action, None)
-                    .as_json()
-                    .map_err(|e| format!("Error serializing message to JSON: {}", e))?;
-
-                println!(
-                    "Taking the order {} for {} {}, with payment method {}",
-                    order.id.unwrap(),
-                    order.fiat_amount,
-                    order.fiat_code,
-                    order.payment_method
-                );
-
-                let event = gift_wrap(&self.my_keys, self.mostro_pubkey, message, None, 0)
-                    .map_err(|e| format!("Error creating event: {}", e))?;
-
-                let msg = ClientMessage::event(event);
-                client.send_msg_to(Settings::get().relays, msg).await?;
+                let order_id = order.id.ok_or("Order ID is missing")?;
+                self.send_take(&order, action, None, client).await?;
+                self.notice = Some(format!(
+                    "Taking the order {} for {} {}",
+                    order_id, order.fiat_amount, order.fiat_code
+                ));
                 self.show_order = false;
             }
         } else {
+            self.trading_mode = Settings::get().trading_mode;
             self.show_order = true;
         }
 
@@ -407,18 +1626,195 @@ impl App {
     async fn handle_event(&mut self, event: &Event, client: Client) {
         if let Event::Key(key) = event {
             if key.kind == KeyEventKind::Press {
+                if self.pay_invoice.is_some() {
+                    if key.code == KeyCode::Esc {
+                        self.pay_invoice = None;
+                    }
+                    return;
+                }
+                if let Some(rating) = &mut self.rating {
+                    match rating.handle_key(key) {
+                        Some(Some(value)) => {
+                            let order_id = rating.order_id;
+                            self.rating = None;
+                            if let Err(e) = self.send_rating(order_id, value, &client).await {
+                                self.notice = Some(format!("Error sending rating: {}", e));
+                            }
+                        }
+                        Some(None) => self.rating = None,
+                        None => {}
+                    }
+                    return;
+                }
+                if let Some(chat) = &mut self.chat {
+                    match chat.handle_key(key) {
+                        Some(ChatOutcome::Send(text)) => {
+                            if let Err(e) = self.send_chat_message(text, &client).await {
+                                self.notice = Some(format!("Error sending message: {}", e));
+                            }
+                        }
+                        Some(ChatOutcome::Closed) => self.chat = None,
+                        None => {}
+                    }
+                    return;
+                }
+                if let Some(trade_confirm) = &mut self.trade_confirm {
+                    match trade_confirm.handle_key(key) {
+                        Some(ConfirmOutcome::Confirmed) => {
+                            let (order_id, action) =
+                                (trade_confirm.order_id, trade_confirm.action.clone());
+                            let result = if self.admin_keys.is_some() && is_admin_action(&action) {
+                                self.send_admin_message(order_id, action.clone(), &client)
+                                    .await
+                            } else {
+                                self.send_order_message(order_id, action.clone(), None, &client)
+                                    .await
+                            };
+                            match result {
+                                Ok(()) => {
+                                    if let Some(trade_confirm) = &mut self.trade_confirm {
+                                        trade_confirm.sent = true;
+                                    }
+                                }
+                                Err(e) => {
+                                    self.trade_confirm = None;
+                                    self.notice = Some(format!("Error sending {}: {}", action, e));
+                                }
+                            }
+                        }
+                        Some(ConfirmOutcome::Closed) => self.trade_confirm = None,
+                        None => {}
+                    }
+                    return;
+                }
+                if self.show_invoice_input {
+                    match key.code {
+                        KeyCode::Esc => self.show_invoice_input = false,
+                        KeyCode::Enter => match self.send_invoice(&client).await {
+                            Ok(()) => {
+                                self.show_invoice_input = false;
+                                self.notice = Some("Invoice sent to Mostro".to_string());
+                            }
+                            Err(e) => self.invoice_error = Some(e.to_string()),
+                        },
+                        _ => {
+                            self.invoice_input.handle_event(&Event::Key(*key));
+                        }
+                    }
+                    return;
+                }
+                if self.order_form.visible {
+                    if let Some((order, trading_mode)) = self.order_form.handle_key(key) {
+                        match self.publish_order(order, trading_mode, &client).await {
+                            Ok(()) => self.notice = Some("Order sent to Mostro".to_string()),
+                            Err(e) => self.notice = Some(format!("Error publishing order: {}", e)),
+                        }
+                    }
+                    return;
+                }
+                if self.selected_tab == 3 {
+                    let editing = self.identity_state.is_editing();
+                    if let Some(command) = self.identity_state.handle_key(key) {
+                        let is_generate = matches!(command, IdentityCommand::Generate);
+                        let is_mnemonic = matches!(
+                            &command,
+                            IdentityCommand::Import(value) if value.split_whitespace().count() > 1
+                        );
+                        match self.update_identity(command, &client).await {
+                            Ok(()) => {
+                                self.identity_state.error = None;
+                                // Show the new words so the user can write them down
+                                if is_generate {
+                                    self.identity_state.mode = IdentityMode::Reveal;
+                                }
+                                // An imported mnemonic may have trades on it
+                                if is_mnemonic {
+                                    self.restore_trades(client.clone());
+                                }
+                            }
+                            Err(e) => {
+                                self.identity_state.error =
+                                    Some(format!("Error updating identity: {}", e))
+                            }
+                        }
+                    }
+                    if editing {
+                        return;
+                    }
+                }
                 match key.code {
                     KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Char('n')
+                        if self.selected_tab == 0
+                            && !self.show_order
+                            && !self.show_amount_input =>
+                    {
+                        self.order_form.open(Settings::get().trading_mode)
+                    }
+                    KeyCode::Char('f') if self.selected_tab == 1 => {
+                        self.confirm_trade_action(Action::FiatSent)
+                    }
+                    KeyCode::Char('r') if self.selected_tab == 1 => {
+                        self.confirm_trade_action(Action::Release)
+                    }
+                    KeyCode::Char('c') if self.selected_tab == 1 => {
+                        self.confirm_trade_action(Action::Cancel)
+                    }
+                    KeyCode::Char('i') if self.selected_tab == 1 => self.open_invoice_input(),
+                    KeyCode::Char('o') if self.selected_tab == 1 => {
+                        self.trades.toggle_pending_only()
+                    }
+                    KeyCode::Char('d') if self.selected_tab == 1 => {
+                        self.confirm_trade_action(Action::Dispute)
+                    }
+                    KeyCode::Char('t') if self.selected_tab == 4 => {
+                        if let Some(dispute) = self.disputes.selected() {
+                            self.trade_confirm =
+                                Some(TradeConfirm::new(dispute.id, Action::AdminTakeDispute));
+                        }
+                    }
+                    KeyCode::Char('s' | 'x') if self.selected_tab == 4 => {
+                        let action = match key.code {
+                            KeyCode::Char('s') => Action::AdminSettle,
+                            _ => Action::AdminCancel,
+                        };
+                        match self.disputes.selected_order().and_then(|o| o.id) {
+                            Some(order_id) => {
+                                self.trade_confirm = Some(TradeConfirm::new(order_id, action))
+                            }
+                            None => {
+                                self.notice = Some("Take the dispute before solving it".to_string())
+                            }
+                        }
+                    }
+                    KeyCode::Char('p') if self.selected_tab == 3 => self.switch_profile = true,
+                    KeyCode::Char('s') if self.selected_tab == 3 => {
+                        self.restore_trades(client.clone())
+                    }
+                    KeyCode::Char('m') if self.selected_tab == 1 => self.open_chat(),
+                    KeyCode::Char('m') if self.show_order => {
+                        self.trading_mode = self.trading_mode.toggle()
+                    }
                     KeyCode::Char('j') | KeyCode::Down => {
-                        if self.selected_tab == 2 {
+                        if self.selected_tab == 1 {
+                            self.trades.scroll_down();
+                        } else if self.selected_tab == 4 {
+                            self.disputes.scroll_down();
+                        } else if self.selected_tab == 2 {
                             self.messages.scroll_down();
+                            self.mark_message_read().await;
                         } else {
                             self.orders.scroll_down();
                         }
                     }
                     KeyCode::Char('k') | KeyCode::Up => {
-                        if self.selected_tab == 2 {
+                        if self.selected_tab == 1 {
+                            self.trades.scroll_up();
+                        } else if self.selected_tab == 4 {
+                            self.disputes.scroll_up();
+                        } else if self.selected_tab == 2 {
                             self.messages.scroll_up();
+                            self.mark_message_read().await;
                         } else {
                             self.orders.scroll_up();
                         }
@@ -426,21 +1822,19 @@ impl App {
                     KeyCode::Left => {
                         if self.selected_tab > 0 {
                             self.selected_tab -= 1;
+                            // Never leave secrets on screen
+                            self.identity_state.mode = IdentityMode::Idle;
                         }
                     }
                     KeyCode::Right => {
-                        if self.selected_tab < 3 {
+                        if self.selected_tab < self.tab_titles().len() - 1 {
                             self.selected_tab += 1;
                             self.show_order = false;
                             self.show_amount_input = false;
                         }
                     }
                     KeyCode::Enter => {
-                        if self.selected_tab == 0
-                            || self.show_order
-                            || self.show_amount_input
-                            || self.show_invoice_input
-                        {
+                        if self.selected_tab == 0 || self.show_order || self.show_amount_input {
                             let order = {
                                 let state = self.orders.state.read().unwrap();
                                 let selected = state.table_state.selected();
@@ -455,7 +1849,8 @@ impl App {
                                                 .take_order(order, Action::TakeSell, &client)
                                                 .await
                                             {
-                                                println!("Error handling order: {}", e);
+                                                self.notice =
+                                                    Some(format!("Error taking order: {}", e));
                                             }
                                         }
                                         OrderKind::Buy => {
@@ -463,7 +1858,8 @@ impl App {
                                                 .take_order(order, Action::TakeBuy, &client)
                                                 .await
                                             {
-                                                println!("Error handling order: {}", e);
+                                                self.notice =
+                                                    Some(format!("Error taking order: {}", e));
                                             }
                                         }
                                     }
@@ -486,13 +1882,29 @@ impl App {
     }
 }
 
+fn is_take(action: &Action) -> bool {
+    matches!(action, Action::TakeSell | Action::TakeBuy)
+}
+
+/// Commands only a solver can send
+fn is_admin_action(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::AdminTakeDispute | Action::AdminSettle | Action::AdminCancel
+    )
+}
+
 #[derive(Debug, Clone, Default)]
 struct MostroListWidget {
     state: Arc<RwLock<MostroListState>>,
+    conversation_keys: ConversationKeys,
 }
 
 #[derive(Debug, Default)]
 struct MostroListState {
+    keys: Vec<Keys>,
+    /// Gift wraps handled since we started, every relay sends us its copy
+    handled: HashSet<String>,
     messages: Vec<DM>,
     loading_state: LoadingState,
     table_state: TableState,
@@ -501,28 +1913,45 @@ struct MostroListState {
 #[derive(Debug)]
 struct DM {
     id: String,
-    kind: Kind,
     sender: PublicKey,
+    /// Action of Mostro messages, None for chat messages
+    action: Option<String>,
     content: String,
     created_at: u64,
+    read: bool,
+}
+
+impl DM {
+    fn from_row(row: &db::Message) -> Option<Self> {
+        Some(Self {
+            id: row.id.clone(),
+            sender: PublicKey::from_str(&row.sender).ok()?,
+            action: row.action.clone(),
+            content: row.content.clone(),
+            created_at: row.created_at as u64,
+            read: row.read,
+        })
+    }
 }
 
 impl MostroListWidget {
     /// Start fetching the orders in the background.
     ///
     /// This method spawns a background task that fetches the orders from the Nostr relay.
-    fn run(&self, client: Client, my_keys: Keys) {
+    /// Messages from Mostro are also sent to `tx` so the app can act on them.
+    fn run(&self, client: Client, tx: UnboundedSender<Received>, pool: SqlitePool) {
         let this = self.clone();
-        tokio::spawn(this.fetch_dms(client, my_keys));
+        tokio::spawn(this.fetch_dms(client, tx, pool));
     }
 
-    async fn fetch_dms(self, client: Client, my_keys: Keys) {
+    async fn fetch_dms(self, client: Client, tx: UnboundedSender<Received>, pool: SqlitePool) {
         self.set_loading_state(LoadingState::Loading);
 
         client
             .handle_notifications(move |notification| {
                 let this = self.clone();
-                let my_keys = my_keys.clone();
+                let tx = tx.clone();
+                let pool = pool.clone();
                 async move {
                     if let RelayPoolNotification::Event {
                         subscription_id,
@@ -530,11 +1959,9 @@ impl MostroListWidget {
                         ..
                     } = notification
                     {
-                        if subscription_id == SubscriptionId::new("messages-sub-id")
-                            && event.kind == Kind::GiftWrap
-                            || event.kind == Kind::PrivateDirectMessage
-                        {
-                            this.handle_message_event(*event, my_keys)?;
+                        // Anyone can send us a gift wrap, skip the ones we can't read
+                        if subscription_id == SubscriptionId::new("messages-sub-id") {
+                            let _ = this.handle_message_event(*event, &tx, &pool).await;
                         }
                     }
                     Ok(false)
@@ -548,6 +1975,74 @@ impl MostroListWidget {
         self.state.write().unwrap().loading_state = state;
     }
 
+    /// Fills the list with the messages saved in the database, newest first
+    fn load(&self, rows: &[db::Message]) {
+        let mut state = self.state.write().unwrap();
+        state.messages = rows.iter().filter_map(DM::from_row).collect();
+        if !state.messages.is_empty() {
+            state.loading_state = LoadingState::Loaded;
+            state.table_state.select(Some(0));
+        }
+    }
+
+    /// Adds a message unless we already have it
+    fn add(&self, dm: DM) {
+        let mut state = self.state.write().unwrap();
+        if state.messages.iter().any(|m| m.id == dm.id) {
+            return;
+        }
+        state.messages.push(dm);
+        state
+            .messages
+            .sort_by_key(|m| std::cmp::Reverse(m.created_at));
+        state.loading_state = LoadingState::Loaded;
+        if state.table_state.selected().is_none() {
+            state.table_state.select(Some(0));
+        }
+    }
+
+    /// Id of the selected message if it wasn't read yet, flagging it as read
+    fn read_selected(&self) -> Option<String> {
+        let mut state = self.state.write().unwrap();
+        let selected = state.table_state.selected()?;
+        let dm = state.messages.get_mut(selected)?;
+        if dm.read {
+            return None;
+        }
+        dm.read = true;
+        Some(dm.id.clone())
+    }
+
+    /// True the first time we get this event in this run
+    fn first_copy(&self, event: &nostr_sdk::Event) -> bool {
+        let mut state = self.state.write().unwrap();
+        state.handled.insert(event.id.to_string())
+    }
+
+    /// Adds keys we can receive messages on
+    fn add_keys(&self, keys: Keys) {
+        self.state.write().unwrap().keys.push(keys);
+    }
+
+    fn set_keys(&self, keys: Vec<Keys>) {
+        self.state.write().unwrap().keys = keys;
+    }
+
+    fn public_keys(&self) -> Vec<PublicKey> {
+        let state = self.state.read().unwrap();
+        state.keys.iter().map(|k| k.public_key()).collect()
+    }
+
+    /// Finds the keys the event was addressed to
+    fn keys_for(&self, event: &nostr_sdk::Event) -> Option<Keys> {
+        let state = self.state.read().unwrap();
+        state
+            .keys
+            .iter()
+            .find(|k| event.public_keys().any(|p| *p == k.public_key()))
+            .cloned()
+    }
+
     fn scroll_down(&self) {
         self.state.write().unwrap().table_state.scroll_down_by(1);
     }
@@ -556,51 +2051,74 @@ impl MostroListWidget {
         self.state.write().unwrap().table_state.scroll_up_by(1);
     }
 
-    fn handle_message_event(&self, event: nostr_sdk::Event, my_keys: Keys) -> Result<()> {
+    async fn handle_message_event(
+        &self,
+        event: nostr_sdk::Event,
+        tx: &UnboundedSender<Received>,
+        pool: &SqlitePool,
+    ) -> Result<()> {
         match event.kind {
             Kind::GiftWrap => {
-                let unwrapped_gift = match unwrap_gift_wrap(Some(&my_keys), None, None, &event) {
+                if !self.first_copy(&event) {
+                    return Ok(());
+                }
+                let my_keys = match self.keys_for(&event) {
+                    Some(keys) => keys,
+                    None => {
+                        return Err("Message is not addressed to any of our keys".into());
+                    }
+                };
+                let unwrapped_gift = match self.conversation_keys.unwrap_gift_wrap(&my_keys, &event)
+                {
                     Ok(u) => u,
                     Err(_) => {
                         return Err("Error unwrapping gift".into());
                     }
                 };
-                let dm = DM {
+                let mostro_pubkey = PublicKey::from_str(Settings::get().mostro_pubkey.as_str())?;
+                let created_at = unwrapped_gift.rumor.created_at.as_u64() as i64;
+                let chat = self.conversation_keys.chat(&my_keys.public_key());
+                let message = if chat.is_none() && unwrapped_gift.sender == mostro_pubkey {
+                    Some(Message::from_json(&unwrapped_gift.rumor.content)?)
+                } else {
+                    None
+                };
+                let kind = message.as_ref().map(|m| m.get_inner_message_kind());
+                let row = db::Message {
                     id: event.id.to_string(),
-                    kind: event.kind,
-                    sender: unwrapped_gift.sender,
+                    rumor_id: unwrapped_gift.rumor.id.map(|id| id.to_string()),
+                    sender: unwrapped_gift.sender.to_string(),
+                    receiver: my_keys.public_key().to_string(),
+                    order_id: kind.and_then(|k| k.id).map(|id| id.to_string()),
+                    action: kind.map(|k| trade::action_name(&k.action)),
                     content: unwrapped_gift.rumor.content.clone(),
-                    created_at: unwrapped_gift.rumor.created_at.as_u64(),
+                    kind: unwrapped_gift.rumor.kind.as_u16(),
+                    // The seal must be signed by the author of the message
+                    verified: unwrapped_gift.rumor.pubkey == unwrapped_gift.sender,
+                    created_at,
+                    read: false,
                 };
-                let mut state = self.state.write().unwrap();
-                state.messages.retain(|m| m.id != dm.id);
-
-                state.messages.push(dm);
-                state.loading_state = LoadingState::Loaded;
-
-                if !state.messages.is_empty() {
-                    state.table_state.select(Some(0));
+                // Relays send us the same messages again on every start
+                if row.create(pool).await.is_err() {
+                    return Err("Error saving message".into());
+                }
+                if let Some(dm) = DM::from_row(&row) {
+                    self.add(dm);
                 }
+
                 // Handle possible messages from mostro
-                let message = Message::from_json(&unwrapped_gift.rumor.content).unwrap();
-                match message.get_inner_message_kind().action {
-                    Action::AddInvoice => {
-                        // TODO: find a way of get a mutable reference to app
-                        // app.show_invoice_input = true;
-                    }
-                    Action::NewOrder => {
-                        todo!("New order created message");
-                    }
-                    Action::CantDo => {
-                        println!("CantDo message");
-                    }
-                    Action::Rate => {
-                        println!("Rate message");
-                    }
-                    _ => {}
+                if let Some(message) = message {
+                    let _ = tx.send(Received::Mostro(Box::new(MostroMessage {
+                        receiver: my_keys.public_key(),
+                        message,
+                        created_at,
+                    })));
+                } else if let Some(message) = ChatMessage::from_row(&row, &self.conversation_keys) {
+                    let _ = tx.send(Received::Chat(message));
                 }
             }
-            Kind::PrivateDirectMessage => todo!("Handle PrivateDirectMessage"),
+            // NIP-17 messages only travel gift wrapped, a bare one isn't for us
+            Kind::PrivateDirectMessage => {}
             _ => {}
         }
 
@@ -629,14 +2147,14 @@ impl Widget for &MostroListWidget {
             } else {
                 dm.sender.to_string()
             };
-            let content = if dm.kind == Kind::GiftWrap {
-                let message = Message::from_json(&dm.content).unwrap();
-                message.get_inner_message_kind().action.to_string()
-            } else {
-                dm.content.clone()
-            };
+            let content = dm.action.clone().unwrap_or_else(|| dm.content.clone());
             let created_at = Local.timestamp_opt(dm.created_at as i64, 0).unwrap();
-            Row::new(vec![sender, content, created_at.to_string()])
+            let row = Row::new(vec![sender, content, created_at.to_string()]);
+            if dm.read {
+                row
+            } else {
+                row.bold()
+            }
         });
         let widths = [
             Constraint::Fill(1),
@@ -673,6 +2191,8 @@ struct OrderListState {
     orders: Vec<Order>,
     loading_state: LoadingState,
     table_state: TableState,
+    /// Last order event we had to skip
+    error: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -695,25 +2215,35 @@ impl OrderListWidget {
     async fn fetch_orders(self, client: Client) {
         self.set_loading_state(LoadingState::Loading);
 
-        client
+        let this = self.clone();
+        let result = client
             .handle_notifications(move |notification| {
-                let this = self.clone();
+                let this = this.clone();
                 async move {
-                    if let RelayPoolNotification::Event {
-                        subscription_id,
-                        event,
-                        ..
-                    } = notification
-                    {
-                        if subscription_id == SubscriptionId::new("orders-sub-id") {
-                            this.handle_order_event(*event)?;
-                        }
-                    }
+                    this.handle_notification(notification);
                     Ok(false)
                 }
             })
-            .await
-            .unwrap();
+            .await;
+        if let Err(e) = result {
+            self.state.write().unwrap().error = Some(e.to_string());
+        }
+    }
+
+    fn handle_notification(&self, notification: RelayPoolNotification) {
+        if let RelayPoolNotification::Event {
+            subscription_id,
+            event,
+            ..
+        } = notification
+        {
+            if subscription_id == SubscriptionId::new("orders-sub-id") {
+                // A malformed event must not stop the other orders
+                if let Err(e) = self.handle_order_event(*event) {
+                    self.state.write().unwrap().error = Some(e.to_string());
+                }
+            }
+        }
     }
 
     fn set_loading_state(&self, state: LoadingState) {
@@ -730,6 +2260,9 @@ impl OrderListWidget {
 
     fn handle_order_event(&self, event: nostr_sdk::Event) -> Result<()> {
         let order = order_from_tags(event)?;
+        if order.id.is_none() || order.kind.is_none() {
+            return Err("Order event without id or kind".into());
+        }
         let mut state = self.state.write().unwrap();
         state.orders.retain(|o| o.id != order.id);
 
@@ -753,11 +2286,15 @@ impl Widget for &OrderListWidget {
         // A block with a right-aligned title with the loading state on the right
         let loading_state = Line::from(format!("{:?}", state.loading_state)).right_aligned();
         let color: Color = Color::from_str("#1D212C").unwrap();
+        let title = match &state.error {
+            Some(error) => format!(" Orders · skipped an event: {} ", error),
+            None => " Orders ".to_string(),
+        };
         let block = Block::bordered()
-            .title(" Orders ")
+            .title(title)
             .title(loading_state)
             .bg(color)
-            .title_bottom("j/k to scroll, ENTER to select order, q to quit");
+            .title_bottom("j/k to scroll, ENTER to select order, n for a new order, q to quit");
 
         // A table with the list of orders
         let rows = state.orders.iter().map(|order| {
@@ -813,4 +2350,44 @@ fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let [area] = vertical.areas(area);
     let [area] = horizontal.areas(area);
     area
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_notification(tags: &[&[&str]]) -> RelayPoolNotification {
+        let tags = tags.iter().map(|t| Tag::parse(t).unwrap());
+        let event = EventBuilder::new(Kind::ParameterizedReplaceable(38383), "", tags)
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        RelayPoolNotification::Event {
+            relay_url: Url::parse("wss://relay.example.com").unwrap(),
+            subscription_id: SubscriptionId::new("orders-sub-id"),
+            event: Box::new(event),
+        }
+    }
+
+    #[test]
+    fn malformed_order_events_are_skipped() {
+        let widget = OrderListWidget::default();
+
+        widget.handle_notification(order_notification(&[
+            &["d", "a8b3a6e0-5d7c-4f4e-9b53-2f0a2e1b6c11"],
+            &["k", "lend"],
+            &["s", "pending"],
+        ]));
+        widget.handle_notification(order_notification(&[&["s", "pending"]]));
+        widget.handle_notification(order_notification(&[
+            &["d", "b1c2d3e4-5d7c-4f4e-9b53-2f0a2e1b6c11"],
+            &["k", "sell"],
+            &["s", "pending"],
+        ]));
+
+        let state = widget.state.read().unwrap();
+        assert_eq!(state.orders.len(), 1);
+        assert_eq!(state.orders[0].kind, Some(OrderKind::Sell));
+        assert!(state.error.is_some());
+    }
+}
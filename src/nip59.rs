@@ -7,7 +7,9 @@ use nostr_sdk::prelude::*;
 ///
 /// # Arguments
 ///
-/// * `sender_keys` - The keys of the sender
+/// * `seal_keys` - The keys that sign the seal, the identity keys to build
+///   reputation or the trade keys for full privacy
+/// * `rumor_keys` - The keys of the rumor author, usually the trade keys
 /// * `receiver` - The public key of the receiver
 /// * `content` - The message
 /// * `expiration` - Time of the expiration of the event
@@ -16,19 +18,41 @@ use nostr_sdk::prelude::*;
 /// Returns a gift wrap event
 ///
 pub fn gift_wrap(
-    sender_keys: &Keys,
+    seal_keys: &Keys,
+    rumor_keys: &Keys,
     receiver: PublicKey,
     content: String,
     expiration: Option<Timestamp>,
     pow: u8,
 ) -> Result<Event, BuilderError> {
     let rumor: UnsignedEvent =
-        EventBuilder::text_note(content, []).to_unsigned_event(sender_keys.public_key());
-    let seal: Event = seal(sender_keys, &receiver, rumor)?.to_event(sender_keys)?;
+        EventBuilder::text_note(content, []).to_unsigned_event(rumor_keys.public_key());
+    let seal: Event = seal(seal_keys, &receiver, rumor)?.to_event(seal_keys)?;
 
     gift_wrap_from_seal(&receiver, &seal, expiration, pow)
 }
 
+/// Creates a NIP-17 private message wrapped for the receiver
+///
+/// The rumor and the seal are both signed with `sender_keys`, so the
+/// receiver can check the message comes from who the seal says. Trade chats
+/// use the shared key of both trade keys as receiver.
+pub fn private_message(
+    sender_keys: &Keys,
+    receiver: PublicKey,
+    content: String,
+) -> Result<Event, BuilderError> {
+    let rumor: UnsignedEvent = EventBuilder::new(
+        Kind::PrivateDirectMessage,
+        content,
+        [Tag::public_key(receiver)],
+    )
+    .to_unsigned_event(sender_keys.public_key());
+    let seal: Event = seal(sender_keys, &receiver, rumor)?.to_event(sender_keys)?;
+
+    gift_wrap_from_seal(&receiver, &seal, None, 0)
+}
+
 pub fn seal(
     sender_keys: &Keys,
     receiver_pubkey: &PublicKey,
@@ -92,26 +116,7 @@ pub fn unwrap_gift_wrap(
             }
         },
     };
-    let b64decoded_content = match general_purpose::STANDARD.decode(gift_wrap.content.as_bytes()) {
-        Ok(b64decoded_content) => b64decoded_content,
-        Err(e) => {
-            return Err(BuilderError::NIP44(
-                nostr_sdk::nips::nip44::Error::NotFound(e.to_string()),
-            ));
-        }
-    };
-    // Decrypt and verify seal
-    let seal = decrypt_to_bytes(&gw_ck, b64decoded_content)?;
-    let seal = String::from_utf8(seal).expect("Found invalid UTF-8");
-    let seal = match Event::from_json(seal) {
-        Ok(seal) => seal,
-        Err(e) => {
-            println!("Error: {:#?}", e);
-            return Err(BuilderError::NIP44(
-                nostr_sdk::nips::nip44::Error::NotFound(e.to_string()),
-            ));
-        }
-    };
+    let seal = open_gift_wrap(&gw_ck, gift_wrap)?;
     let seal_ck = match keys {
         Some(keys) => ConversationKey::derive(keys.secret_key(), &seal.pubkey),
         None => match seal_ck {
@@ -125,6 +130,34 @@ pub fn unwrap_gift_wrap(
             }
         },
     };
+
+    open_seal(&seal_ck, seal)
+}
+
+/// Decrypts the outer layer of a gift wrap and returns the seal inside
+pub fn open_gift_wrap(gw_ck: &ConversationKey, gift_wrap: &Event) -> Result<Event, BuilderError> {
+    let b64decoded_content = match general_purpose::STANDARD.decode(gift_wrap.content.as_bytes()) {
+        Ok(b64decoded_content) => b64decoded_content,
+        Err(e) => {
+            return Err(BuilderError::NIP44(
+                nostr_sdk::nips::nip44::Error::NotFound(e.to_string()),
+            ));
+        }
+    };
+    // Decrypt and verify seal
+    let seal = decrypt_to_bytes(gw_ck, b64decoded_content)?;
+    let seal = String::from_utf8(seal)
+        .map_err(|e| BuilderError::NIP44(nostr_sdk::nips::nip44::Error::NotFound(e.to_string())))?;
+    match Event::from_json(seal) {
+        Ok(seal) => Ok(seal),
+        Err(e) => Err(BuilderError::NIP44(
+            nostr_sdk::nips::nip44::Error::NotFound(e.to_string()),
+        )),
+    }
+}
+
+/// Decrypts a seal and returns the rumor with the seal author as sender
+pub fn open_seal(seal_ck: &ConversationKey, seal: Event) -> Result<UnwrappedGift, BuilderError> {
     let b64decoded_content = match general_purpose::STANDARD.decode(seal.content.as_bytes()) {
         Ok(b64decoded_content) => b64decoded_content,
         Err(e) => {
@@ -134,11 +167,34 @@ pub fn unwrap_gift_wrap(
         }
     };
     // Decrypt rumor
-    let rumor = decrypt_to_bytes(&seal_ck, b64decoded_content)?;
-    let rumor = String::from_utf8(rumor).expect("Found invalid UTF-8");
+    let rumor = decrypt_to_bytes(seal_ck, b64decoded_content)?;
+    let rumor = String::from_utf8(rumor)
+        .map_err(|e| BuilderError::NIP44(nostr_sdk::nips::nip44::Error::NotFound(e.to_string())))?;
 
     Ok(UnwrappedGift {
         sender: seal.pubkey,
         rumor: UnsignedEvent::from_json(rumor)?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gift_wrap_with_invalid_utf8_is_an_error() {
+        let receiver = Keys::generate();
+        let sender = Keys::generate();
+        let ck = ConversationKey::derive(sender.secret_key(), &receiver.public_key());
+        let content = encrypt_to_bytes(&ck, [0xff, 0xfe, 0xfd]).unwrap();
+        let gift_wrap = EventBuilder::new(
+            Kind::GiftWrap,
+            general_purpose::STANDARD.encode(content),
+            [Tag::public_key(receiver.public_key())],
+        )
+        .to_event(&sender)
+        .unwrap();
+
+        assert!(unwrap_gift_wrap(Some(&receiver), None, None, &gift_wrap).is_err());
+    }
+}
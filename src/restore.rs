@@ -0,0 +1,238 @@
+use crate::db::{Order, Restore, TradeKey};
+use crate::identity::{Identity, FIRST_TRADE_INDEX};
+use crate::nip59::unwrap_gift_wrap;
+use crate::settings::Settings;
+use crate::trade::{is_finished, mark_applied, Role};
+use crate::util::order_from_tags;
+use mostro_core::message::{Action, Content, Message};
+use mostro_core::order::Status;
+use mostro_core::NOSTR_REPLACEABLE_EVENT_KIND;
+use nostr_sdk::prelude::*;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Consecutive unused trade keys after which we stop scanning
+const GAP_LIMIT: u32 = 20;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Trade found while scanning the derived keys
+struct FoundTrade {
+    order_id: Uuid,
+    role: &'static str,
+    created_at: i64,
+    /// Actions Mostro sent to this trade and when
+    messages: Vec<(Action, i64)>,
+    /// Token Mostro gave us in the last dispute of the trade
+    dispute_token: Option<(i64, u16)>,
+}
+
+impl FoundTrade {
+    /// Status of the last dispute of the trade, the order event doesn't show it
+    fn dispute_status(&self) -> Option<Status> {
+        let mut messages: Vec<&(Action, i64)> = self.messages.iter().collect();
+        messages.sort_by_key(|(_, created_at)| *created_at);
+        messages.iter().rev().find_map(|(action, _)| match action {
+            Action::DisputeInitiatedByYou
+            | Action::DisputeInitiatedByPeer
+            | Action::AdminTookDispute => Some(Status::Dispute),
+            Action::AdminSettled => Some(Status::SettledByAdmin),
+            Action::AdminCanceled => Some(Status::CanceledByAdmin),
+            _ => None,
+        })
+    }
+}
+
+/// True if the trades of this mnemonic were already restored on this machine
+pub async fn was_restored(identity: &Identity, pool: &SqlitePool) -> Result<bool> {
+    let pubkey = restore_id(identity)?;
+
+    Ok(Restore::by_pubkey(pool, &pubkey).await?.is_some())
+}
+
+/// Public key of the first trade key, it only depends on the mnemonic
+fn restore_id(identity: &Identity) -> Result<String> {
+    Ok(identity
+        .trade_keys(FIRST_TRADE_INDEX)?
+        .public_key()
+        .to_string())
+}
+
+/// Rebuilds the trades of this identity from the mnemonic
+///
+/// The Mostro protocol we speak has no restore-session message, so we scan the trade keys
+/// derived from the mnemonic and fetch the messages Mostro sent to them,
+/// stopping after `GAP_LIMIT` keys in a row without messages. The orders
+/// found are fetched from their public events and saved with their trade
+/// keys in the local database, with the status and our token of their last
+/// dispute. Orders whose event can't be read are skipped.
+///
+/// Returns the number of trades restored
+pub async fn restore_session(
+    client: &Client,
+    identity: &Identity,
+    pool: &SqlitePool,
+    mostro_pubkey: PublicKey,
+) -> Result<usize> {
+    let mut trades: BTreeMap<u32, FoundTrade> = BTreeMap::new();
+    let mut start = FIRST_TRADE_INDEX;
+    loop {
+        let keys = (start..start + GAP_LIMIT)
+            .map(|index| Ok((index, identity.trade_keys(index)?)))
+            .collect::<Result<Vec<(u32, Keys)>>>()?;
+        let filter = Filter::new()
+            .pubkeys(keys.iter().map(|(_, k)| k.public_key()))
+            .kind(Kind::GiftWrap);
+        let events = client
+            .get_events_of(vec![filter], EventSource::relays(Some(FETCH_TIMEOUT)))
+            .await?;
+        if events.is_empty() {
+            break;
+        }
+
+        for event in events {
+            let Some((index, trade_keys)) = keys
+                .iter()
+                .find(|(_, k)| event.public_keys().any(|p| *p == k.public_key()))
+            else {
+                continue;
+            };
+            let Ok(unwrapped) = unwrap_gift_wrap(Some(trade_keys), None, None, &event) else {
+                continue;
+            };
+            if unwrapped.sender != mostro_pubkey {
+                continue;
+            }
+            let Ok(message) = Message::from_json(&unwrapped.rumor.content) else {
+                continue;
+            };
+            let inner = message.get_inner_message_kind();
+            let Some(order_id) = inner.id else {
+                continue;
+            };
+            let created_at = unwrapped.rumor.created_at.as_u64() as i64;
+            let trade = trades.entry(*index).or_insert(FoundTrade {
+                order_id,
+                role: "taker",
+                created_at,
+                messages: vec![],
+                dispute_token: None,
+            });
+            // Only the maker gets the confirmation of a new order
+            if inner.action == Action::NewOrder {
+                trade.role = "maker";
+            }
+            if let Some(Content::Dispute(_, Some(token))) = &inner.content {
+                if trade.dispute_token.is_none_or(|(at, _)| at < created_at) {
+                    trade.dispute_token = Some((created_at, *token));
+                }
+            }
+            trade.created_at = trade.created_at.min(created_at);
+            trade.messages.push((inner.action.clone(), created_at));
+        }
+        start += GAP_LIMIT;
+    }
+    // Saved once everything was restored, so a failed restore runs again
+    let restore = Restore::new(restore_id(identity)?);
+    if trades.is_empty() {
+        restore.create(pool).await?;
+        return Ok(0);
+    }
+
+    // Orders are replaceable events, relays send us their last status
+    let order_ids: Vec<String> = trades.values().map(|t| t.order_id.to_string()).collect();
+    let filter = Filter::new()
+        .author(mostro_pubkey)
+        .kind(Kind::ParameterizedReplaceable(NOSTR_REPLACEABLE_EVENT_KIND))
+        .identifiers(order_ids);
+    let events = client
+        .get_events_of(vec![filter], EventSource::relays(Some(FETCH_TIMEOUT)))
+        .await?;
+    for event in events {
+        let Ok(order) = order_from_tags(event) else {
+            continue;
+        };
+        Order::from(&order).create(pool).await?;
+    }
+
+    let known = TradeKey::all(pool).await?;
+    let trading_mode = Settings::get().trading_mode;
+    for (index, trade) in &trades {
+        let order_id = trade.order_id.to_string();
+        if known
+            .iter()
+            .any(|k| k.trade_index == *index as i64 || k.order_id.as_ref() == Some(&order_id))
+        {
+            continue;
+        }
+        let mut trade_key = TradeKey::new(
+            *index as i64,
+            Some(order_id.clone()),
+            trade.role,
+            trading_mode,
+        );
+        trade_key.created_at = trade.created_at;
+        trade_key.create(pool).await?;
+        let Some(mut order) = Order::by_id(pool, &order_id).await? else {
+            continue;
+        };
+        let status = order
+            .status
+            .as_deref()
+            .and_then(|s| Status::from_str(s).ok());
+        if let (Some(status), Some(dispute)) = (status, trade.dispute_status()) {
+            if !is_finished(status) {
+                order.status = Some(dispute.to_string());
+            }
+        }
+        if let Some((_, token)) = trade.dispute_token {
+            match Role::of(&order, &trade_key) {
+                Role::Buyer => order.buyer_token = Some(token),
+                Role::Seller => order.seller_token = Some(token),
+            }
+        }
+        order.save(pool).await?;
+        if let Some(status) = &order.status {
+            mark_applied(pool, &order_id, status, &trade.messages).await?;
+        }
+    }
+    restore.create(pool).await?;
+
+    Ok(trades.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(messages: &[(Action, i64)]) -> FoundTrade {
+        FoundTrade {
+            order_id: Uuid::nil(),
+            role: "taker",
+            created_at: 0,
+            messages: messages.to_vec(),
+            dispute_token: None,
+        }
+    }
+
+    #[test]
+    fn last_dispute_message_gives_the_status() {
+        assert_eq!(trade(&[(Action::FiatSentOk, 1)]).dispute_status(), None);
+        assert_eq!(
+            trade(&[(Action::FiatSentOk, 1), (Action::DisputeInitiatedByPeer, 2)]).dispute_status(),
+            Some(Status::Dispute)
+        );
+        // Relays don't send the messages in order
+        assert_eq!(
+            trade(&[
+                (Action::AdminSettled, 3),
+                (Action::DisputeInitiatedByYou, 2),
+                (Action::AdminTookDispute, 2),
+            ])
+            .dispute_status(),
+            Some(Status::SettledByAdmin)
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::SETTINGS;
 use config::{Config, ConfigError, Environment, File};
+use nostr_sdk::Keys;
 use serde::Deserialize;
 #[cfg(unix)]
 use std::os::unix::ffi::OsStrExt;
@@ -8,14 +9,79 @@ use std::os::windows::ffi::OsStrExt;
 use std::{
     env,
     ffi::OsString,
-    fs,
+    fmt, fs,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::RwLock,
 };
 
+/// Profile kept directly in `~/.mostrui`, other profiles live in `~/.mostrui/profiles/<name>`
+pub const DEFAULT_PROFILE: &str = "default";
+
+static PROFILE: RwLock<Option<String>> = RwLock::new(None);
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub mostro_pubkey: String,
     pub relays: Vec<String>,
+    /// Default trading mode, it can be changed for each trade
+    #[serde(default)]
+    pub trading_mode: TradingMode,
+    /// Shows the disputes of this Mostro so a solver can manage them, the
+    /// solver key is kept in the keystore
+    #[serde(default)]
+    pub admin_mode: bool,
+}
+
+/// Which keys sign the seal of the messages sent to Mostro
+///
+/// https://mostro.network/protocol/key_management.html
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradingMode {
+    /// The identity key signs the seal, so our trades build reputation
+    #[default]
+    Reputation,
+    /// The trade key signs the seal, trades can't be linked to our identity
+    Privacy,
+}
+
+impl TradingMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            TradingMode::Reputation => TradingMode::Privacy,
+            TradingMode::Privacy => TradingMode::Reputation,
+        }
+    }
+
+    /// Picks the keys that sign the seal, the rumor is always authored by the trade keys
+    pub fn seal_keys<'a>(&self, identity_keys: &'a Keys, trade_keys: &'a Keys) -> &'a Keys {
+        match self {
+            TradingMode::Reputation => identity_keys,
+            TradingMode::Privacy => trade_keys,
+        }
+    }
+}
+
+impl fmt::Display for TradingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradingMode::Reputation => write!(f, "reputation"),
+            TradingMode::Privacy => write!(f, "privacy"),
+        }
+    }
+}
+
+impl FromStr for TradingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reputation" => Ok(TradingMode::Reputation),
+            "privacy" => Ok(TradingMode::Privacy),
+            _ => Err(format!("Unknown trading mode: {}", s)),
+        }
+    }
 }
 
 #[cfg(windows)]
@@ -59,10 +125,8 @@ impl Settings {
             }
         };
         if !Path::new(&file_name).exists() {
-            println!("Settings file not found: {}", file_name);
             return Err(ConfigError::NotFound(file_name));
         }
-        println!("Loading settings from: {}", file_name);
 
         let s = Config::builder()
             .add_source(File::with_name(&file_name).required(true))
@@ -76,20 +140,81 @@ impl Settings {
     }
 
     pub fn get() -> Self {
-        SETTINGS.get().unwrap().clone()
+        SETTINGS.read().unwrap().clone().unwrap()
     }
 }
 
+/// Sets the global settings, replacing the ones of the previous profile
 pub fn init_global_settings(settings: Settings) {
-    SETTINGS.set(settings).unwrap()
+    *SETTINGS.write().unwrap() = Some(settings);
 }
 
-pub fn get_settings_path() -> String {
+pub fn get_profile() -> String {
+    PROFILE
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+/// Selects the profile used by `get_settings_path`
+pub fn set_profile(profile: &str) {
+    *PROFILE.write().unwrap() = Some(profile.to_string());
+}
+
+/// Profile names are used as directory names
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Lists the named profiles, the default one is not included
+pub fn list_profiles() -> Vec<String> {
+    let profiles_path = format!("{}/profiles", get_mostrui_path());
+    let mut profiles: Vec<String> = match fs::read_dir(profiles_path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => vec![],
+    };
+    profiles.sort();
+
+    profiles
+}
+
+/// Root mostrui directory, which also holds the default profile
+fn get_mostrui_path() -> String {
     let home_dir = env::var("HOME").expect("Couldn't get HOME directory");
-    let settings_path = format!("{}/.mostrui", home_dir);
+    let mostrui_path = format!("{}/.mostrui", home_dir);
+    if !Path::new(&mostrui_path).exists() {
+        fs::create_dir(&mostrui_path).expect("Couldn't create mostrui directory");
+    }
+
+    mostrui_path
+}
+
+/// Directory of the active profile, with its settings, keystore and database
+///
+/// New profiles start with a copy of the default profile settings.
+pub fn get_settings_path() -> String {
+    let mostrui_path = get_mostrui_path();
+    let profile = get_profile();
+    if profile == DEFAULT_PROFILE {
+        return mostrui_path;
+    }
+
+    let settings_path = format!("{}/profiles/{}", mostrui_path, profile);
     if !Path::new(&settings_path).exists() {
-        fs::create_dir(&settings_path).expect("Couldn't create mostrui directory");
-        println!("Directory {} created.", settings_path);
+        fs::create_dir_all(&settings_path).expect("Couldn't create profile directory");
+        let default_settings = format!("{}/settings.toml", mostrui_path);
+        if Path::new(&default_settings).exists() {
+            fs::copy(default_settings, format!("{}/settings.toml", settings_path))
+                .expect("Couldn't copy default settings to the profile");
+        }
     }
 
     settings_path
@@ -0,0 +1,731 @@
+use crate::db::{Order, TradeKey, Transition};
+use mostro_core::message::Action;
+use mostro_core::order::Status;
+use nostr_sdk::prelude::rand::rngs::OsRng;
+use nostr_sdk::prelude::rand::RngCore;
+use nostr_sdk::prelude::serde_json;
+use sqlx::SqlitePool;
+use std::error::Error;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Time Mostro gives to pay the hold invoice or add an invoice by default
+pub const STEP_WINDOW_SECS: i64 = 15 * 60;
+
+/// Seconds left under which we warn that a deadline is close
+pub const EXPIRATION_WARNING_SECS: i64 = 5 * 60;
+
+/// Side we are on in a trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Buyer,
+    Seller,
+}
+
+impl Role {
+    /// Our side of the trade, from the order kind and whether we made or took it
+    pub fn of(order: &Order, trade_key: &TradeKey) -> Self {
+        match (order.kind.as_deref(), trade_key.role.as_str()) {
+            (Some("buy"), "maker") | (Some("sell"), "taker") => Role::Buyer,
+            _ => Role::Seller,
+        }
+    }
+}
+
+/// What a message from Mostro does to a trade
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    /// The trade moves to this status, it may be the one it already had
+    To(Status),
+    /// The message makes no sense for our role in the current status
+    Illegal,
+}
+
+/// Outcome of applying a message from Mostro to one of our trades
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applied {
+    /// We already applied this message, relays send old messages again on start
+    Seen,
+    /// The message was applied, the trade went from one status to the other
+    Moved { from: Status, to: Status },
+    /// The message was flagged and the trade kept its status
+    Flagged(Status),
+}
+
+/// State machine of one of our trades
+///
+/// The status only changes when Mostro tells us, the messages it sends drive
+/// the transitions and the status decides what we can do next.
+#[derive(Debug, Clone)]
+pub struct Trade {
+    pub role: Role,
+    pub maker: bool,
+    pub status: Status,
+}
+
+impl Trade {
+    pub fn new(order: &Order, trade_key: &TradeKey) -> Result<Self, String> {
+        let status = order.status.as_deref().unwrap_or("pending");
+        let status = Status::from_str(status).map_err(|_| format!("Unknown status {}", status))?;
+
+        Ok(Self {
+            role: Role::of(order, trade_key),
+            maker: trade_key.role == "maker",
+            status,
+        })
+    }
+
+    /// Status the trade moves to when Mostro sends us `action`
+    pub fn next(&self, action: &Action) -> Next {
+        next_status(self.role, self.status, action)
+    }
+
+    /// Commands we can send on this trade right now
+    pub fn allowed_actions(&self) -> Vec<Action> {
+        match (self.status, self.role) {
+            (Status::Pending, _) if self.maker => vec![Action::Cancel],
+            (Status::WaitingPayment, _) => vec![Action::Cancel],
+            (Status::WaitingBuyerInvoice, Role::Buyer) => vec![Action::AddInvoice, Action::Cancel],
+            (Status::WaitingBuyerInvoice, Role::Seller) => vec![Action::Cancel],
+            (Status::Active, Role::Buyer) => {
+                vec![Action::FiatSent, Action::Cancel, Action::Dispute]
+            }
+            (Status::Active, Role::Seller) => vec![Action::Cancel, Action::Dispute],
+            (Status::FiatSent, Role::Buyer) => vec![Action::Cancel, Action::Dispute],
+            (Status::FiatSent, Role::Seller) => {
+                vec![Action::Release, Action::Cancel, Action::Dispute]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Tells why `action` can't be sent on this trade right now
+    pub fn check(&self, action: &Action) -> Result<(), String> {
+        if self.allowed_actions().contains(action) {
+            return Ok(());
+        }
+        match (action, self.role) {
+            (Action::FiatSent | Action::AddInvoice, Role::Seller) => {
+                Err("Only the buyer can do that".to_string())
+            }
+            (Action::Release, Role::Buyer) => {
+                Err("Only the seller can release the sats".to_string())
+            }
+            (Action::Release, Role::Seller) => {
+                Err("Wait until the buyer tells you the fiat was sent".to_string())
+            }
+            (Action::Cancel, _) if self.status == Status::Pending => {
+                Err("Wait until Mostro answers the take".to_string())
+            }
+            _ => Err(format!("Not allowed while the trade is {}", self.status)),
+        }
+    }
+}
+
+/// Deadline of a step Mostro asked us to do at `asked_at`, `expires_at` is
+/// the expiration Mostro sent with the order if any
+pub fn step_deadline(asked_at: i64, expires_at: Option<i64>) -> i64 {
+    match expires_at {
+        Some(expires_at) if expires_at > asked_at => expires_at.min(asked_at + STEP_WINDOW_SECS),
+        _ => asked_at + STEP_WINDOW_SECS,
+    }
+}
+
+/// True in the statuses that end if nobody acts before `expires_at`
+pub fn has_deadline(status: Status) -> bool {
+    matches!(
+        status,
+        Status::Pending | Status::WaitingPayment | Status::WaitingBuyerInvoice
+    )
+}
+
+/// True once nothing else can happen in the trade
+pub fn is_finished(status: Status) -> bool {
+    matches!(
+        status,
+        Status::Success
+            | Status::Canceled
+            | Status::CanceledByAdmin
+            | Status::SettledByAdmin
+            | Status::CompletedByAdmin
+            | Status::CooperativelyCanceled
+            | Status::Expired
+    )
+}
+
+/// True if there is little time left before `deadline`
+pub fn is_expiring(deadline: i64) -> bool {
+    deadline - chrono::Utc::now().timestamp() < EXPIRATION_WARNING_SECS
+}
+
+/// Transition table of a trade, see the Mostro protocol for the flows
+///
+/// https://mostro.network/protocol/
+fn next_status(role: Role, status: Status, action: &Action) -> Next {
+    use Status::*;
+
+    let open = matches!(
+        status,
+        Pending | WaitingPayment | WaitingBuyerInvoice | Active | FiatSent
+    );
+    let to = match (action, role, status) {
+        (Action::PayInvoice, Role::Seller, Pending | WaitingBuyerInvoice) => WaitingPayment,
+        (Action::WaitingSellerToPay, Role::Buyer, Pending | WaitingBuyerInvoice) => WaitingPayment,
+        (Action::AddInvoice, Role::Buyer, Pending | WaitingPayment | WaitingBuyerInvoice) => {
+            WaitingBuyerInvoice
+        }
+        // The payment to the buyer failed, Mostro asks for a new invoice
+        (Action::AddInvoice, Role::Buyer, SettledHoldInvoice) => SettledHoldInvoice,
+        (Action::WaitingBuyerInvoice, Role::Seller, Pending | WaitingPayment) => {
+            WaitingBuyerInvoice
+        }
+        (Action::HoldInvoicePaymentAccepted, Role::Buyer, WaitingPayment | WaitingBuyerInvoice)
+        | (Action::BuyerTookOrder, Role::Seller, WaitingPayment | WaitingBuyerInvoice) => Active,
+        (Action::BuyerInvoiceAccepted, Role::Buyer, _) if open => status,
+        (Action::FiatSentOk, _, Active) => FiatSent,
+        (Action::HoldInvoicePaymentSettled, Role::Seller, FiatSent)
+        | (Action::Released, Role::Buyer, FiatSent) => SettledHoldInvoice,
+        (Action::PurchaseCompleted, _, FiatSent | SettledHoldInvoice) => Success,
+        (Action::Rate, _, SettledHoldInvoice | Success) => status,
+        (
+            Action::CooperativeCancelInitiatedByYou | Action::CooperativeCancelInitiatedByPeer,
+            _,
+            _,
+        ) if open => status,
+        (Action::CooperativeCancelAccepted, _, _) if open => CooperativelyCanceled,
+        (Action::Canceled | Action::HoldInvoicePaymentCanceled, _, _) if open => Canceled,
+        (Action::DisputeInitiatedByYou | Action::DisputeInitiatedByPeer, _, Active | FiatSent) => {
+            Dispute
+        }
+        (Action::AdminTookDispute, _, Dispute) => Dispute,
+        (Action::AdminSettled, _, Dispute) => SettledByAdmin,
+        (Action::AdminCanceled, _, Dispute) => CanceledByAdmin,
+        // Answers that don't move the trade
+        (
+            Action::NewOrder
+            | Action::CantDo
+            | Action::RateReceived
+            | Action::PaymentFailed
+            | Action::InvoiceUpdated
+            | Action::NotAllowedByStatus
+            | Action::OutOfRangeFiatAmount
+            | Action::OutOfRangeSatsAmount
+            | Action::InvalidSatsAmount
+            | Action::IncorrectInvoiceAmount
+            | Action::IsNotYourOrder
+            | Action::IsNotYourDispute
+            | Action::NotFound,
+            _,
+            _,
+        ) => status,
+        _ => return Next::Illegal,
+    };
+
+    Next::To(to)
+}
+
+/// Kebab-case name of the action, as Mostro sends it
+pub fn action_name(action: &Action) -> String {
+    match serde_json::to_value(action) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => action.to_string(),
+    }
+}
+
+pub fn parse_action(name: &str) -> Option<Action> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+/// Applies a message from Mostro to one of our trades and saves the new status
+///
+/// Every message is saved as a transition. Messages that arrive later than
+/// newer ones are put back in the order Mostro sent them and the trade is
+/// replayed from its first known status.
+pub async fn apply(
+    pool: &SqlitePool,
+    order: &mut Order,
+    trade_key: &TradeKey,
+    action: &Action,
+    created_at: i64,
+) -> Result<Applied, Box<dyn Error>> {
+    let order_id = order.id.clone().ok_or("Order ID is missing")?;
+    let name = action_name(action);
+    let mut transitions = Transition::by_order_id(pool, &order_id).await?;
+    if transitions
+        .iter()
+        .any(|t| t.action == name && t.created_at == created_at)
+    {
+        return Ok(Applied::Seen);
+    }
+
+    let trade = Trade::new(order, trade_key)?;
+    let from = trade.status;
+    let late = transitions
+        .last()
+        .is_some_and(|t| t.created_at > created_at);
+    let (to, accepted) = if late {
+        let mut status = match transitions.first() {
+            Some(first) => Status::from_str(&first.from_status).unwrap_or(from),
+            None => from,
+        };
+        transitions.push(Transition {
+            order_id: order_id.clone(),
+            action: name,
+            from_status: String::new(),
+            to_status: String::new(),
+            accepted: false,
+            created_at,
+        });
+        transitions.sort_by_key(|t| t.created_at);
+        Transition::delete_by_order_id(pool, &order_id).await?;
+        let mut accepted = false;
+        for transition in transitions.iter_mut() {
+            let next = parse_action(&transition.action)
+                .map_or(Next::Illegal, |a| next_status(trade.role, status, &a));
+            transition.from_status = status.to_string();
+            transition.accepted = next != Next::Illegal;
+            if let Next::To(to) = next {
+                status = to;
+            }
+            transition.to_status = status.to_string();
+            if transition.created_at == created_at && transition.action == action_name(action) {
+                accepted = transition.accepted;
+            }
+            transition.create(pool).await?;
+        }
+        (status, accepted)
+    } else {
+        let (to, accepted) = match trade.next(action) {
+            Next::To(to) => (to, true),
+            Next::Illegal => (from, false),
+        };
+        Transition {
+            order_id: order_id.clone(),
+            action: name,
+            from_status: from.to_string(),
+            to_status: to.to_string(),
+            accepted,
+            created_at,
+        }
+        .create(pool)
+        .await?;
+        (to, accepted)
+    };
+
+    if to != from {
+        order.set_status(to.to_string()).save(pool).await?;
+    }
+    if accepted {
+        Ok(Applied::Moved { from, to })
+    } else {
+        Ok(Applied::Flagged(to))
+    }
+}
+
+/// Saves the messages of a restored trade as applied
+///
+/// The status of a restored trade comes from its order event, so the
+/// messages sent before the restore don't move it when relays send them again.
+pub async fn mark_applied(
+    pool: &SqlitePool,
+    order_id: &str,
+    status: &str,
+    messages: &[(Action, i64)],
+) -> Result<(), sqlx::Error> {
+    for (action, created_at) in messages {
+        Transition {
+            order_id: order_id.to_string(),
+            action: action_name(action),
+            from_status: status.to_string(),
+            to_status: status.to_string(),
+            accepted: true,
+            created_at: *created_at,
+        }
+        .create(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Seconds we wait for Mostro to answer a request before showing it timed out
+pub const REQUEST_TIMEOUT: i64 = 60;
+
+/// Message we sent to Mostro that is still waiting for an answer
+#[derive(Debug, Clone)]
+pub struct PendingRequest {
+    pub request_id: u64,
+    /// None for new orders, Mostro gives them an id in its answer
+    pub order_id: Option<Uuid>,
+    pub action: Action,
+    pub sent_at: i64,
+}
+
+impl PendingRequest {
+    pub fn new(order_id: Option<Uuid>, action: Action) -> Self {
+        Self {
+            request_id: OsRng.next_u64(),
+            order_id,
+            action,
+            sent_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    pub fn timed_out(&self, now: i64) -> bool {
+        now - self.sent_at > REQUEST_TIMEOUT
+    }
+    /// Whether Mostro answers this request with `action` when it succeeds,
+    /// used to match the answers that don't carry the request id
+    pub fn answered_by(&self, action: &Action) -> bool {
+        let answers: &[Action] = match self.action {
+            Action::NewOrder => &[Action::NewOrder],
+            Action::TakeSell => &[Action::AddInvoice, Action::WaitingSellerToPay],
+            Action::TakeBuy => &[Action::PayInvoice, Action::WaitingBuyerInvoice],
+            Action::AddInvoice => &[
+                Action::WaitingSellerToPay,
+                Action::HoldInvoicePaymentAccepted,
+                Action::InvoiceUpdated,
+            ],
+            Action::FiatSent => &[Action::FiatSentOk],
+            Action::Release => &[
+                Action::HoldInvoicePaymentSettled,
+                Action::Released,
+                Action::PurchaseCompleted,
+            ],
+            Action::Cancel => &[
+                Action::Canceled,
+                Action::CooperativeCancelInitiatedByYou,
+                Action::CooperativeCancelAccepted,
+            ],
+            Action::Dispute => &[Action::DisputeInitiatedByYou],
+            Action::RateUser => &[Action::RateReceived],
+            Action::AdminCancel => &[Action::AdminCanceled],
+            Action::AdminSettle => &[Action::AdminSettled],
+            Action::AdminTakeDispute => &[Action::AdminTookDispute],
+            _ => &[],
+        };
+
+        answers.contains(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TradingMode;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const ORDER_ID: &str = "a8b3a6e0-5d7c-4f4e-9b53-2f0a2e1b6c11";
+
+    fn fold(role: Role, actions: &[Action]) -> Vec<Next> {
+        let mut status = Status::Pending;
+        actions
+            .iter()
+            .map(|action| {
+                let next = next_status(role, status, action);
+                if let Next::To(to) = next {
+                    status = to;
+                }
+                next
+            })
+            .collect()
+    }
+
+    /// Order we took, `kind` is the order kind so taking a sell order makes us the buyer
+    fn taken(kind: &str, status: Status) -> (Order, TradeKey) {
+        let order = Order {
+            id: Some(ORDER_ID.to_string()),
+            kind: Some(kind.to_string()),
+            status: Some(status.to_string()),
+            ..Default::default()
+        };
+        let trade_key = TradeKey::new(
+            1,
+            Some(ORDER_ID.to_string()),
+            "taker",
+            TradingMode::Reputation,
+        );
+        (order, trade_key)
+    }
+
+    async fn pool() -> SqlitePool {
+        // Every connection to an in-memory database gets its own one
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::create_tables(&pool).await.unwrap();
+        pool
+    }
+
+    #[test]
+    fn buyer_goes_from_pending_to_success() {
+        let actions = [
+            Action::AddInvoice,
+            Action::WaitingSellerToPay,
+            Action::HoldInvoicePaymentAccepted,
+            Action::FiatSentOk,
+            Action::Released,
+            Action::PurchaseCompleted,
+        ];
+        let expected = [
+            Status::WaitingBuyerInvoice,
+            Status::WaitingPayment,
+            Status::Active,
+            Status::FiatSent,
+            Status::SettledHoldInvoice,
+            Status::Success,
+        ];
+
+        assert_eq!(fold(Role::Buyer, &actions), expected.map(Next::To).to_vec());
+    }
+
+    #[test]
+    fn seller_goes_from_pending_to_success() {
+        let actions = [
+            Action::PayInvoice,
+            Action::BuyerTookOrder,
+            Action::FiatSentOk,
+            Action::HoldInvoicePaymentSettled,
+            Action::PurchaseCompleted,
+        ];
+        let expected = [
+            Status::WaitingPayment,
+            Status::Active,
+            Status::FiatSent,
+            Status::SettledHoldInvoice,
+            Status::Success,
+        ];
+
+        assert_eq!(
+            fold(Role::Seller, &actions),
+            expected.map(Next::To).to_vec()
+        );
+    }
+
+    #[test]
+    fn actions_for_the_other_side_are_illegal() {
+        assert_eq!(
+            next_status(Role::Buyer, Status::Pending, &Action::PayInvoice),
+            Next::Illegal
+        );
+        assert_eq!(
+            next_status(Role::Seller, Status::FiatSent, &Action::Released),
+            Next::Illegal
+        );
+        assert_eq!(
+            next_status(Role::Seller, Status::Pending, &Action::AddInvoice),
+            Next::Illegal
+        );
+    }
+
+    #[test]
+    fn actions_out_of_order_are_illegal() {
+        assert_eq!(
+            next_status(Role::Buyer, Status::Pending, &Action::FiatSentOk),
+            Next::Illegal
+        );
+        assert_eq!(
+            next_status(Role::Buyer, Status::Pending, &Action::DisputeInitiatedByYou),
+            Next::Illegal
+        );
+        assert_eq!(
+            next_status(Role::Seller, Status::Success, &Action::Canceled),
+            Next::Illegal
+        );
+    }
+
+    #[test]
+    fn cancels_and_disputes_end_open_trades() {
+        assert_eq!(
+            next_status(Role::Buyer, Status::Active, &Action::Canceled),
+            Next::To(Status::Canceled)
+        );
+        assert_eq!(
+            next_status(
+                Role::Seller,
+                Status::FiatSent,
+                &Action::CooperativeCancelAccepted
+            ),
+            Next::To(Status::CooperativelyCanceled)
+        );
+        assert_eq!(
+            fold(
+                Role::Seller,
+                &[
+                    Action::PayInvoice,
+                    Action::BuyerTookOrder,
+                    Action::DisputeInitiatedByPeer,
+                    Action::AdminSettled,
+                ]
+            )
+            .last(),
+            Some(&Next::To(Status::SettledByAdmin))
+        );
+    }
+
+    #[test]
+    fn errors_keep_the_status() {
+        assert_eq!(
+            next_status(Role::Buyer, Status::Active, &Action::NotAllowedByStatus),
+            Next::To(Status::Active)
+        );
+    }
+
+    #[test]
+    fn allowed_actions_follow_the_role() {
+        let (order, trade_key) = taken("sell", Status::WaitingBuyerInvoice);
+        let buyer = Trade::new(&order, &trade_key).unwrap();
+        assert_eq!(buyer.role, Role::Buyer);
+        assert!(buyer.check(&Action::AddInvoice).is_ok());
+        assert!(buyer.check(&Action::Release).is_err());
+
+        let (order, trade_key) = taken("buy", Status::FiatSent);
+        let seller = Trade::new(&order, &trade_key).unwrap();
+        assert_eq!(seller.role, Role::Seller);
+        assert!(seller.check(&Action::Release).is_ok());
+        assert!(seller.check(&Action::FiatSent).is_err());
+    }
+
+    #[test]
+    fn action_names_round_trip() {
+        let name = action_name(&Action::HoldInvoicePaymentAccepted);
+        assert_eq!(name, "hold-invoice-payment-accepted");
+        assert_eq!(
+            parse_action(&name),
+            Some(Action::HoldInvoicePaymentAccepted)
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_moves_the_trade_and_saves_it() {
+        let pool = pool().await;
+        let (mut order, trade_key) = taken("sell", Status::Pending);
+        order.create(&pool).await.unwrap();
+
+        let applied = apply(&pool, &mut order, &trade_key, &Action::AddInvoice, 100)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            applied,
+            Applied::Moved {
+                from: Status::Pending,
+                to: Status::WaitingBuyerInvoice
+            }
+        );
+        let saved = Order::by_id(&pool, ORDER_ID).await.unwrap().unwrap();
+        assert_eq!(saved.status.as_deref(), Some("waiting-buyer-invoice"));
+    }
+
+    #[tokio::test]
+    async fn apply_sees_replayed_messages() {
+        let pool = pool().await;
+        let (mut order, trade_key) = taken("sell", Status::Pending);
+        order.create(&pool).await.unwrap();
+
+        apply(&pool, &mut order, &trade_key, &Action::AddInvoice, 100)
+            .await
+            .unwrap();
+        let applied = apply(&pool, &mut order, &trade_key, &Action::AddInvoice, 100)
+            .await
+            .unwrap();
+
+        assert_eq!(applied, Applied::Seen);
+        assert_eq!(
+            Transition::by_order_id(&pool, ORDER_ID)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_flags_illegal_messages() {
+        let pool = pool().await;
+        let (mut order, trade_key) = taken("sell", Status::Pending);
+        order.create(&pool).await.unwrap();
+
+        let applied = apply(&pool, &mut order, &trade_key, &Action::Released, 100)
+            .await
+            .unwrap();
+
+        assert_eq!(applied, Applied::Flagged(Status::Pending));
+        let transitions = Transition::by_order_id(&pool, ORDER_ID).await.unwrap();
+        assert!(!transitions[0].accepted);
+        let saved = Order::by_id(&pool, ORDER_ID).await.unwrap().unwrap();
+        assert_eq!(saved.status.as_deref(), Some("pending"));
+    }
+
+    #[tokio::test]
+    async fn apply_replays_late_messages_in_order() {
+        let pool = pool().await;
+        let (mut order, trade_key) = taken("sell", Status::Pending);
+        order.create(&pool).await.unwrap();
+
+        // WaitingSellerToPay arrives before the AddInvoice Mostro sent first
+        let early = apply(
+            &pool,
+            &mut order,
+            &trade_key,
+            &Action::WaitingSellerToPay,
+            200,
+        )
+        .await
+        .unwrap();
+        let late = apply(&pool, &mut order, &trade_key, &Action::AddInvoice, 100)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            early,
+            Applied::Moved {
+                from: Status::Pending,
+                to: Status::WaitingPayment
+            }
+        );
+        assert_eq!(
+            late,
+            Applied::Moved {
+                from: Status::WaitingPayment,
+                to: Status::WaitingPayment
+            }
+        );
+        let transitions = Transition::by_order_id(&pool, ORDER_ID).await.unwrap();
+        let actions: Vec<&str> = transitions.iter().map(|t| t.action.as_str()).collect();
+        assert_eq!(actions, ["add-invoice", "waiting-seller-to-pay"]);
+        assert!(transitions.iter().all(|t| t.accepted));
+    }
+
+    #[test]
+    fn requests_are_answered_only_by_their_replies() {
+        let cancel = PendingRequest::new(Some(Uuid::nil()), Action::Cancel);
+
+        assert!(cancel.answered_by(&Action::Canceled));
+        assert!(cancel.answered_by(&Action::CooperativeCancelInitiatedByYou));
+        assert!(!cancel.answered_by(&Action::BuyerTookOrder));
+        assert!(!cancel.answered_by(&Action::HoldInvoicePaymentAccepted));
+    }
+
+    #[tokio::test]
+    async fn forgetting_the_trade_keys_forgets_their_trades() {
+        let pool = pool().await;
+        let (mut order, trade_key) = taken("sell", Status::Pending);
+        order.create(&pool).await.unwrap();
+        trade_key.create(&pool).await.unwrap();
+        apply(&pool, &mut order, &trade_key, &Action::AddInvoice, 100)
+            .await
+            .unwrap();
+
+        TradeKey::delete_all(&pool).await.unwrap();
+
+        assert!(TradeKey::all(&pool).await.unwrap().is_empty());
+        assert!(Order::by_id(&pool, ORDER_ID).await.unwrap().is_none());
+        assert!(Transition::by_order_id(&pool, ORDER_ID)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -3,6 +3,7 @@ use nostr_sdk::prelude::*;
 use std::str::FromStr;
 use uuid::Uuid;
 
+/// Reads an order from the tags of its event, errors if a tag has an invalid value
 pub fn order_from_tags(event: Event) -> Result<Order> {
     let tags = event.tags;
     let mut order = Order {
@@ -11,8 +12,11 @@ pub fn order_from_tags(event: Event) -> Result<Order> {
     };
     for tag in tags {
         let t = tag.as_slice();
-        let v = t.get(1).unwrap().as_str();
-        match t.first().unwrap().as_str() {
+        let [name, v, ..] = t else {
+            continue;
+        };
+        let v = v.as_str();
+        match name.as_str() {
             "d" => {
                 let id = v.parse::<Uuid>();
                 let id = match id {
@@ -22,7 +26,8 @@ pub fn order_from_tags(event: Event) -> Result<Order> {
                 order.id = Some(id);
             }
             "k" => {
-                order.kind = Some(OrderKind::from_str(v).unwrap());
+                let kind = OrderKind::from_str(v).map_err(|_| format!("Invalid kind {}", v))?;
+                order.kind = Some(kind);
             }
             "f" => {
                 order.fiat_code = v.to_string();
@@ -31,16 +36,15 @@ pub fn order_from_tags(event: Event) -> Result<Order> {
                 order.status = Some(Status::from_str(v).unwrap_or(Status::Dispute));
             }
             "amt" => {
-                order.amount = v.parse::<i64>().unwrap();
+                order.amount = v.parse::<i64>()?;
             }
             "fa" => {
                 if v.contains('.') {
                     continue;
                 }
-                let max = t.get(2);
-                if max.is_some() {
+                if let Some(max) = t.get(2) {
                     order.min_amount = v.parse::<i64>().ok();
-                    order.max_amount = max.unwrap().parse::<i64>().ok();
+                    order.max_amount = max.parse::<i64>().ok();
                 } else {
                     let fa = v.parse::<i64>();
                     order.fiat_amount = fa.unwrap_or(0);
@@ -50,7 +54,11 @@ pub fn order_from_tags(event: Event) -> Result<Order> {
                 order.payment_method = v.to_string();
             }
             "premium" => {
-                order.premium = v.parse::<i64>().unwrap();
+                order.premium = v.parse::<i64>()?;
+            }
+            // NIP-40, Mostro removes the order from the book after this time
+            "expiration" => {
+                order.expires_at = v.parse::<i64>().ok();
             }
             _ => {}
         }
@@ -58,3 +66,163 @@ pub fn order_from_tags(event: Event) -> Result<Order> {
 
     Ok(order)
 }
+
+/// Checks the syntax of a Lightning address like `user@domain.com`
+pub fn is_valid_ln_address(address: &str) -> bool {
+    let Some((user, domain)) = address.split_once('@') else {
+        return false;
+    };
+    let user_valid = !user.is_empty()
+        && user
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.+".contains(c));
+    let domain_valid = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+
+    user_valid && domain_valid
+}
+
+/// Checks the syntax of a bech32 encoded LNURL
+pub fn is_valid_lnurl(lnurl: &str) -> bool {
+    let lnurl = lnurl.to_lowercase();
+    match lnurl.strip_prefix("lnurl1") {
+        Some(data) => {
+            data.len() >= 6
+                && data
+                    .chars()
+                    .all(|c| "qpzry9x8gf2tvdw0s3jn54khce6mua7l".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Invoice as Mostro expects it, wallets often copy it as a `lightning:` URI
+pub fn normalize_invoice(invoice: &str) -> String {
+    let invoice = invoice.trim().to_lowercase();
+    match invoice.strip_prefix("lightning:") {
+        Some(invoice) => invoice.to_string(),
+        None => invoice,
+    }
+}
+
+/// Checks the syntax of a BOLT11 invoice, the node checks the rest
+pub fn is_valid_bolt11(invoice: &str) -> bool {
+    let invoice = normalize_invoice(invoice);
+    match invoice.rsplit_once('1') {
+        Some((hrp, data)) => {
+            hrp.starts_with("ln")
+                && data.len() > 6
+                && data
+                    .chars()
+                    .all(|c| "qpzry9x8gf2tvdw0s3jn54khce6mua7l".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Time left until `deadline`, `mm:ss` under an hour and `1h 05m` above
+pub fn countdown(deadline: i64) -> String {
+    let secs = deadline - chrono::Utc::now().timestamp();
+    if secs <= 0 {
+        return "expired".to_string();
+    }
+    if secs < 3600 {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example invoice from the BOLT11 spec
+    const INVOICE: &str = "lnbc1pvjluezsp5zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zyg3zygspp5qqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqqqsyqcyq5rqwzqfqypqdpl2pkx2ctnv5sxxmmwwd5kgetjypeh2ursdae8g6twvus8g6rfwvs8qun0dfjkxaq9qrsgq357wnc5r2ueh7ck6q93dj32dlqnls087fxdwk8qakdyafkq3yap9us6v52vjjsrvywa6rt52cm9r9zqt8r2t7mlcwspyetp5h2tztugp9lfyql";
+
+    fn order_event(tags: &[&[&str]]) -> Event {
+        let tags = tags.iter().map(|t| Tag::parse(t).unwrap());
+        EventBuilder::new(Kind::ParameterizedReplaceable(38383), "", tags)
+            .to_event(&Keys::generate())
+            .unwrap()
+    }
+
+    #[test]
+    fn order_from_tags_reads_the_order() {
+        let event = order_event(&[
+            &["d", "a8b3a6e0-5d7c-4f4e-9b53-2f0a2e1b6c11"],
+            &["k", "sell"],
+            &["f", "EUR"],
+            &["s", "pending"],
+            &["amt", "0"],
+            &["fa", "10", "50"],
+            &["pm", "sepa"],
+            &["premium", "2"],
+        ]);
+
+        let order = order_from_tags(event).unwrap();
+
+        assert_eq!(order.kind, Some(OrderKind::Sell));
+        assert_eq!(order.status, Some(Status::Pending));
+        assert_eq!((order.min_amount, order.max_amount), (Some(10), Some(50)));
+        assert_eq!(order.premium, 2);
+    }
+
+    #[test]
+    fn order_from_tags_errors_on_invalid_values() {
+        assert!(order_from_tags(order_event(&[&["k", "lend"]])).is_err());
+        assert!(order_from_tags(order_event(&[&["amt", "lots"]])).is_err());
+        assert!(order_from_tags(order_event(&[&["premium", "1.5"]])).is_err());
+        // Tags without value are skipped
+        assert!(order_from_tags(order_event(&[&["y"], &["k", "buy"]])).is_ok());
+    }
+
+    #[test]
+    fn normalize_invoice_strips_the_uri_prefix() {
+        let uri = format!("  LIGHTNING:{}\n", INVOICE.to_uppercase());
+        assert_eq!(normalize_invoice(&uri), INVOICE);
+        assert_eq!(normalize_invoice(INVOICE), INVOICE);
+    }
+
+    #[test]
+    fn bolt11_invoices() {
+        assert!(is_valid_bolt11(INVOICE));
+        assert!(is_valid_bolt11(&format!("lightning:{}", INVOICE)));
+        assert!(is_valid_bolt11(&INVOICE.to_uppercase()));
+        assert!(!is_valid_bolt11(""));
+        assert!(!is_valid_bolt11("lnbc1short"));
+        // `b` isn't in the bech32 charset
+        assert!(!is_valid_bolt11(&INVOICE.replace('q', "b")));
+        assert!(!is_valid_bolt11(&INVOICE.replacen("ln", "xx", 1)));
+        assert!(!is_valid_bolt11("satoshi@mostro.network"));
+    }
+
+    #[test]
+    fn ln_addresses() {
+        assert!(is_valid_ln_address("satoshi@mostro.network"));
+        assert!(is_valid_ln_address("sat.oshi+tips_1@pay.my-domain.com"));
+        assert!(!is_valid_ln_address("satoshi"));
+        assert!(!is_valid_ln_address("@mostro.network"));
+        assert!(!is_valid_ln_address("satoshi@localhost"));
+        assert!(!is_valid_ln_address("satoshi@.mostro.network"));
+        assert!(!is_valid_ln_address("satoshi@mostro.network."));
+        assert!(!is_valid_ln_address("Satoshi@mostro.network"));
+        assert!(!is_valid_ln_address("sat oshi@mostro.network"));
+        assert!(!is_valid_ln_address("satoshi@mostro_network.com"));
+    }
+
+    #[test]
+    fn lnurls() {
+        let lnurl = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+        assert!(is_valid_lnurl(lnurl));
+        assert!(is_valid_lnurl(&lnurl.to_lowercase()));
+        assert!(!is_valid_lnurl("lnurl1"));
+        assert!(!is_valid_lnurl("lnurl1abc"));
+        assert!(!is_valid_lnurl("satoshi@mostro.network"));
+        assert!(!is_valid_lnurl(INVOICE));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::conversation::ConversationKeys;
+use chrono::{Local, TimeZone};
+use nostr_sdk::prelude::*;
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+use std::str::FromStr;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+use uuid::Uuid;
+
+/// NIP-17 message between one of our trade keys and the counterparty
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    /// Id of the gift wrap that carried it, wrapped to the shared key of the chat
+    pub id: String,
+    /// Our trade key in the trade, each trade has its own thread
+    pub trade_pubkey: PublicKey,
+    pub peer: PublicKey,
+    pub content: String,
+    pub created_at: i64,
+    pub mine: bool,
+}
+
+impl ChatMessage {
+    /// Chat message in a saved or just received row, the receiver is the
+    /// shared key of its chat
+    ///
+    /// Only NIP-17 messages sealed by their author and sent by one of the
+    /// two sides of the trade are chat messages.
+    pub fn from_row(row: &crate::db::Message, chats: &ConversationKeys) -> Option<Self> {
+        // Only Mostro messages have an action
+        if row.action.is_some() || row.kind != Kind::PrivateDirectMessage.as_u16() || !row.verified
+        {
+            return None;
+        }
+        let sender = PublicKey::from_str(&row.sender).ok()?;
+        let chat = chats.chat(&PublicKey::from_str(&row.receiver).ok()?)?;
+        if sender != chat.trade_pubkey && sender != chat.peer {
+            return None;
+        }
+
+        Some(Self {
+            id: row.id.clone(),
+            trade_pubkey: chat.trade_pubkey,
+            peer: chat.peer,
+            content: row.content.clone(),
+            created_at: row.created_at,
+            mine: sender == chat.trade_pubkey,
+        })
+    }
+}
+
+/// What the user did in the chat pane
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChatOutcome {
+    Send(String),
+    Closed,
+}
+
+/// Chat with the counterparty of one of our trades
+#[derive(Debug)]
+pub struct ChatPane {
+    pub order_id: Uuid,
+    pub trade_keys: Keys,
+    pub peer: PublicKey,
+    /// Keys both sides wrap the messages to
+    pub shared_keys: Keys,
+    /// Shared secret key in hex, the user can give it to a solver
+    shared_key: String,
+    show_key: bool,
+    input: Input,
+    /// Lines scrolled up from the newest message
+    scroll: u16,
+}
+
+impl ChatPane {
+    /// Opens the chat of `trade_keys` with `peer`, its keys are kept in `chats`
+    pub fn new(
+        order_id: Uuid,
+        trade_keys: Keys,
+        peer: PublicKey,
+        chats: &ConversationKeys,
+    ) -> Result<Self> {
+        let shared_keys = chats.shared_keys(&trade_keys, &peer)?;
+        let shared_key = chats.export(&trade_keys, &peer)?;
+
+        Ok(Self {
+            order_id,
+            trade_keys,
+            peer,
+            shared_keys,
+            shared_key,
+            show_key: false,
+            input: Input::default(),
+            scroll: 0,
+        })
+    }
+
+    /// True if the message belongs to this trade's thread
+    pub fn shows(&self, message: &ChatMessage) -> bool {
+        message.trade_pubkey == self.trade_keys.public_key() && message.peer == self.peer
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<ChatOutcome> {
+        match key.code {
+            KeyCode::Esc => return Some(ChatOutcome::Closed),
+            KeyCode::Enter => {
+                let text = self.input.value().trim().to_string();
+                if !text.is_empty() {
+                    self.input.reset();
+                    self.scroll = 0;
+                    return Some(ChatOutcome::Send(text));
+                }
+            }
+            KeyCode::Tab => self.show_key = !self.show_key,
+            KeyCode::Up | KeyCode::PageUp => self.scroll = self.scroll.saturating_add(1),
+            KeyCode::Down | KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(1),
+            _ => {
+                self.input.handle_event(&Event::Key(*key));
+            }
+        }
+
+        None
+    }
+}
+
+pub struct ChatWidget<'a> {
+    pub pane: &'a ChatPane,
+    pub messages: Vec<&'a ChatMessage>,
+}
+
+impl<'a> ChatWidget<'a> {
+    pub fn new(pane: &'a ChatPane, messages: &'a [ChatMessage]) -> Self {
+        let mut messages: Vec<&ChatMessage> = messages.iter().filter(|m| pane.shows(m)).collect();
+        messages.sort_by_key(|m| m.created_at);
+
+        Self { pane, messages }
+    }
+}
+
+impl Widget for ChatWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 70, 80);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let block = Block::bordered()
+            .title(format!("Chat · order {}", self.pane.order_id))
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom("ESC to close, ENTER to send, up/down to scroll, TAB for the shared key");
+        let inner_area = block.inner(popup_area);
+        Clear.render(popup_area, buf);
+        block.render(popup_area, buf);
+
+        let key_height = if self.pane.show_key { 5 } else { 0 };
+        let [history_area, key_area, input_area] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(key_height),
+            Constraint::Length(3),
+        ])
+        .areas(inner_area);
+        let lines: Vec<Line> = if self.messages.is_empty() {
+            vec![Line::raw(
+                "No messages yet, they are end-to-end encrypted between your trade keys.",
+            )]
+        } else {
+            self.messages
+                .iter()
+                .map(|message| {
+                    let time = Local
+                        .timestamp_opt(message.created_at, 0)
+                        .single()
+                        .map(|t| t.format("%H:%M").to_string())
+                        .unwrap_or_default();
+                    if message.mine {
+                        Line::from(format!("{} you: {}", time, message.content)).white()
+                    } else {
+                        Line::from(format!("{} peer: {}", time, message.content)).cyan()
+                    }
+                })
+                .collect()
+        };
+        // Keep the newest messages in view unless the user scrolled up
+        let text_height: usize = lines
+            .iter()
+            .map(|l| {
+                l.width()
+                    .max(1)
+                    .div_ceil(history_area.width.max(1) as usize)
+            })
+            .sum();
+        let bottom = (text_height as u16).saturating_sub(history_area.height);
+        let history = Paragraph::new(lines)
+            .wrap(Wrap { trim: true })
+            .scroll((bottom.saturating_sub(self.pane.scroll), 0));
+        history.render(history_area, buf);
+
+        if self.pane.show_key {
+            let key = Paragraph::new(vec![
+                Line::raw(self.pane.shared_key.clone()).yellow(),
+                Line::raw(
+                    "It opens this chat only, give it to the solver of a dispute on this trade.",
+                ),
+            ])
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title("Shared key"));
+            key.render(key_area, buf);
+        }
+
+        let input = Paragraph::new(self.pane.input.value())
+            .white()
+            .block(Block::bordered().title("Message"));
+        input.render(input_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Message;
+
+    fn row(sender: PublicKey, receiver: PublicKey, kind: Kind, verified: bool) -> Message {
+        Message {
+            id: "wrap".to_string(),
+            rumor_id: None,
+            sender: sender.to_string(),
+            receiver: receiver.to_string(),
+            order_id: None,
+            action: None,
+            content: "hi".to_string(),
+            kind: kind.as_u16(),
+            verified,
+            created_at: 0,
+            read: false,
+        }
+    }
+
+    #[test]
+    fn from_row_keeps_the_messages_of_both_sides() {
+        let (mine, peer) = (Keys::generate(), Keys::generate());
+        let chats = ConversationKeys::default();
+        let shared = chats.shared_keys(&mine, &peer.public_key()).unwrap();
+
+        let sent = row(
+            mine.public_key(),
+            shared.public_key(),
+            Kind::PrivateDirectMessage,
+            true,
+        );
+        let got = row(
+            peer.public_key(),
+            shared.public_key(),
+            Kind::PrivateDirectMessage,
+            true,
+        );
+
+        assert!(ChatMessage::from_row(&sent, &chats).unwrap().mine);
+        let message = ChatMessage::from_row(&got, &chats).unwrap();
+        assert!(!message.mine);
+        assert_eq!(message.trade_pubkey, mine.public_key());
+        assert_eq!(message.peer, peer.public_key());
+    }
+
+    #[test]
+    fn from_row_skips_what_the_live_path_skips() {
+        let (mine, peer, other) = (Keys::generate(), Keys::generate(), Keys::generate());
+        let chats = ConversationKeys::default();
+        let shared = chats.shared_keys(&mine, &peer.public_key()).unwrap();
+        let shared = shared.public_key();
+
+        let unverified = row(peer.public_key(), shared, Kind::PrivateDirectMessage, false);
+        let note = row(peer.public_key(), shared, Kind::TextNote, true);
+        let stranger = row(other.public_key(), shared, Kind::PrivateDirectMessage, true);
+        let not_a_chat = row(
+            peer.public_key(),
+            mine.public_key(),
+            Kind::PrivateDirectMessage,
+            true,
+        );
+
+        for row in [unverified, note, stranger, not_a_chat] {
+            assert!(ChatMessage::from_row(&row, &chats).is_none());
+        }
+    }
+
+    #[test]
+    fn chat_pane_shows_the_key_of_its_chat() {
+        let (mine, peer) = (Keys::generate(), Keys::generate());
+        let chats = ConversationKeys::default();
+
+        let pane = ChatPane::new(Uuid::nil(), mine.clone(), peer.public_key(), &chats).unwrap();
+
+        assert_eq!(
+            Keys::parse(&pane.shared_key).unwrap().public_key(),
+            pane.shared_keys.public_key()
+        );
+        assert!(chats.chat(&pane.shared_keys.public_key()).is_some());
+    }
+}
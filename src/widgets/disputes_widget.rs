@@ -0,0 +1,235 @@
+use chrono::{Local, TimeZone};
+use mostro_core::order::SmallOrder;
+use nostr_sdk::prelude::*;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::palette::tailwind::{BLUE, SLATE},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{
+        Block, Cell, HighlightSpacing, Paragraph, Row, StatefulWidget, Table, TableState, Widget,
+        Wrap,
+    },
+};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+pub const DISPUTES_SUB_ID: &str = "disputes-sub-id";
+
+/// Dispute published by Mostro
+#[derive(Debug, Clone)]
+pub struct Dispute {
+    pub id: Uuid,
+    pub status: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct DisputeListState {
+    pub disputes: Vec<Dispute>,
+    /// Orders of the disputes we took, Mostro sends them when we take one
+    pub orders: Vec<(Uuid, SmallOrder)>,
+    /// Why the last dispute event we skipped was malformed
+    pub error: Option<String>,
+    pub table_state: TableState,
+}
+
+/// Disputes of this Mostro, only shown in admin mode
+#[derive(Debug, Clone, Default)]
+pub struct DisputeListWidget {
+    pub state: Arc<RwLock<DisputeListState>>,
+}
+
+impl DisputeListWidget {
+    /// Start listening to dispute events in the background.
+    pub fn run(&self, client: Client) {
+        let this = self.clone();
+        tokio::spawn(this.fetch_disputes(client));
+    }
+
+    async fn fetch_disputes(self, client: Client) {
+        let this = self.clone();
+        let result = client
+            .handle_notifications(move |notification| {
+                let this = this.clone();
+                async move {
+                    if let RelayPoolNotification::Event {
+                        subscription_id,
+                        event,
+                        ..
+                    } = notification
+                    {
+                        if subscription_id == SubscriptionId::new(DISPUTES_SUB_ID) {
+                            // A malformed event must not stop the other disputes
+                            if let Err(e) = this.handle_dispute_event(*event) {
+                                this.state.write().unwrap().error = Some(e.to_string());
+                            }
+                        }
+                    }
+                    Ok(false)
+                }
+            })
+            .await;
+        if let Err(e) = result {
+            self.state.write().unwrap().error = Some(e.to_string());
+        }
+    }
+
+    fn handle_dispute_event(&self, event: Event) -> Result<()> {
+        let mut id = None;
+        let mut status = String::new();
+        for tag in event.tags.iter() {
+            match tag.as_slice() {
+                [name, value, ..] if name == "d" => id = Uuid::parse_str(value).ok(),
+                [name, value, ..] if name == "s" => status = value.clone(),
+                _ => {}
+            }
+        }
+        let id = id.ok_or(format!("Dispute event {} without id", event.id))?;
+        let dispute = Dispute {
+            id,
+            status,
+            created_at: event.created_at.as_u64() as i64,
+        };
+
+        let mut state = self.state.write().unwrap();
+        state.disputes.retain(|d| d.id != dispute.id);
+        // Solved disputes drop out of the list
+        if matches!(dispute.status.as_str(), "initiated" | "in-progress") {
+            state.disputes.push(dispute);
+        }
+        state
+            .disputes
+            .sort_by_key(|d| std::cmp::Reverse(d.created_at));
+        if state.table_state.selected().is_none() {
+            state.table_state.select(Some(0));
+        }
+
+        Ok(())
+    }
+
+    pub fn selected(&self) -> Option<Dispute> {
+        let state = self.state.read().unwrap();
+        state.disputes.get(state.table_state.selected()?).cloned()
+    }
+
+    /// Order of the selected dispute, once we took it
+    pub fn selected_order(&self) -> Option<SmallOrder> {
+        let dispute = self.selected()?;
+        let state = self.state.read().unwrap();
+        state
+            .orders
+            .iter()
+            .find(|(id, _)| *id == dispute.id)
+            .map(|(_, order)| order.clone())
+    }
+
+    pub fn add_order(&self, dispute_id: Uuid, order: SmallOrder) {
+        let mut state = self.state.write().unwrap();
+        state.orders.retain(|(id, _)| *id != dispute_id);
+        state.orders.push((dispute_id, order));
+    }
+
+    pub fn scroll_down(&self) {
+        self.state.write().unwrap().table_state.scroll_down_by(1);
+    }
+
+    pub fn scroll_up(&self) {
+        self.state.write().unwrap().table_state.scroll_up_by(1);
+    }
+}
+
+impl Widget for &DisputeListWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [list_area, details_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(12)]).areas(area);
+        let order = self.selected_order();
+        let mut state = self.state.write().unwrap();
+
+        let color: Color = Color::from_str("#1D212C").unwrap();
+        let title = match &state.error {
+            Some(error) => format!(" Disputes · skipped an event: {} ", error),
+            None => " Disputes ".to_string(),
+        };
+        let block = Block::bordered()
+            .title(title)
+            .bg(color)
+            .title_bottom("j/k to scroll, t to take, s to settle, x to cancel, q to quit");
+        let rows = state.disputes.iter().map(|dispute| {
+            let created_at = Local
+                .timestamp_opt(dispute.created_at, 0)
+                .single()
+                .map(|t| t.to_string())
+                .unwrap_or_default();
+            Row::new(vec![
+                dispute.id.to_string(),
+                dispute.status.clone(),
+                created_at,
+            ])
+        });
+        let widths = [
+            Constraint::Length(38),
+            Constraint::Length(16),
+            Constraint::Fill(1),
+        ];
+        let color = Color::from_str("#304F00").unwrap();
+        let header_style = Style::default().fg(SLATE.c200).bg(color);
+        let selected_style = Style::default().fg(BLUE.c400);
+        let header = ["Id", "Status", "Created At"]
+            .into_iter()
+            .map(Cell::from)
+            .collect::<Row>()
+            .style(header_style)
+            .height(1);
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(block)
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_symbol(">>")
+            .row_highlight_style(selected_style);
+        StatefulWidget::render(table, list_area, buf, &mut state.table_state);
+
+        let lines = match order {
+            Some(order) => vec![
+                Line::raw(format!(
+                    "Order: {}",
+                    order.id.map(|id| id.to_string()).unwrap_or_default()
+                )),
+                Line::raw(format!(
+                    "{} {} sats for {} {} by {}",
+                    order.kind.map(|k| k.to_string()).unwrap_or_default(),
+                    order.amount,
+                    order.fiat_amount,
+                    order.fiat_code,
+                    order.payment_method
+                )),
+                Line::raw(format!(
+                    "Buyer: {}",
+                    order.master_buyer_pubkey.unwrap_or_default()
+                )),
+                Line::raw(format!(
+                    "Buyer token: {}",
+                    order.buyer_token.map(|t| t.to_string()).unwrap_or_default()
+                )),
+                Line::raw(format!(
+                    "Seller: {}",
+                    order.master_seller_pubkey.unwrap_or_default()
+                )),
+                Line::raw(format!(
+                    "Seller token: {}",
+                    order
+                        .seller_token
+                        .map(|t| t.to_string())
+                        .unwrap_or_default()
+                )),
+            ],
+            None => vec![Line::raw("Take the dispute to see the order details")],
+        };
+        let details = Paragraph::new(lines)
+            .block(Block::bordered().title(" Dispute details "))
+            .wrap(Wrap { trim: true });
+        details.render(details_area, buf);
+    }
+}
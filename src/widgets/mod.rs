@@ -1 +1,9 @@
+pub mod chat_widget;
+pub mod disputes_widget;
+pub mod order_form_widget;
+pub mod pay_invoice_widget;
+pub mod profile_widget;
+pub mod rating_widget;
 pub mod settings_widget;
+pub mod setup_widget;
+pub mod trades_widget;
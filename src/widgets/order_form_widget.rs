@@ -0,0 +1,324 @@
+use crate::settings::TradingMode;
+use crate::util::{is_valid_ln_address, is_valid_lnurl};
+use mostro_core::order::{Kind as OrderKind, SmallOrder, Status};
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    layout::Rect,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+use std::str::FromStr;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+
+/// Fields of the new order form, in the order they are shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OrderField {
+    #[default]
+    Kind,
+    FiatCode,
+    FiatAmount,
+    PaymentMethod,
+    Premium,
+    SatsAmount,
+    /// Only for buy orders
+    LnAddress,
+    TradingMode,
+}
+
+impl OrderField {
+    const ALL: [OrderField; 8] = [
+        OrderField::Kind,
+        OrderField::FiatCode,
+        OrderField::FiatAmount,
+        OrderField::PaymentMethod,
+        OrderField::Premium,
+        OrderField::SatsAmount,
+        OrderField::LnAddress,
+        OrderField::TradingMode,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            OrderField::Kind => "Kind",
+            OrderField::FiatCode => "Fiat code",
+            OrderField::FiatAmount => "Fiat amount",
+            OrderField::PaymentMethod => "Payment method",
+            OrderField::Premium => "Premium (%)",
+            OrderField::SatsAmount => "Sats amount",
+            OrderField::LnAddress => "LN address",
+            OrderField::TradingMode => "Trading mode",
+        }
+    }
+
+    fn hint(&self) -> &'static str {
+        match self {
+            OrderField::Kind => "SPACE to switch between buy and sell",
+            OrderField::FiatCode => "Currency code, e.g. USD",
+            OrderField::FiatAmount => "A fixed amount like 100, or a range like 100-500",
+            OrderField::PaymentMethod => "e.g. bank transfer, cash",
+            OrderField::Premium => "Positive for a premium, negative for a discount",
+            OrderField::SatsAmount => "Leave empty to use the market price",
+            OrderField::LnAddress => {
+                "Optional Lightning address or LNURL to receive the sats without sending an invoice"
+            }
+            OrderField::TradingMode => "SPACE to switch between reputation and privacy",
+        }
+    }
+
+    /// Fields that apply to an order of this kind
+    fn for_kind(kind: OrderKind) -> Vec<OrderField> {
+        Self::ALL
+            .into_iter()
+            .filter(|f| kind == OrderKind::Buy || *f != OrderField::LnAddress)
+            .collect()
+    }
+
+    fn next(&self, kind: OrderKind) -> Self {
+        let fields = Self::for_kind(kind);
+        let i = fields.iter().position(|f| f == self).unwrap_or(0);
+        fields[(i + 1) % fields.len()]
+    }
+
+    fn previous(&self, kind: OrderKind) -> Self {
+        let fields = Self::for_kind(kind);
+        let i = fields.iter().position(|f| f == self).unwrap_or(0);
+        fields[(i + fields.len() - 1) % fields.len()]
+    }
+}
+
+/// State of the new order form, open while `visible` is true
+#[derive(Debug)]
+pub struct OrderFormState {
+    pub visible: bool,
+    pub kind: OrderKind,
+    pub focused: OrderField,
+    pub fiat_code: Input,
+    pub fiat_amount: Input,
+    pub payment_method: Input,
+    pub premium: Input,
+    pub sats_amount: Input,
+    pub ln_address: Input,
+    /// Trading mode of this order, the one in the settings by default
+    pub trading_mode: TradingMode,
+    pub error: Option<String>,
+}
+
+impl Default for OrderFormState {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            kind: OrderKind::Sell,
+            focused: OrderField::default(),
+            fiat_code: Input::default(),
+            fiat_amount: Input::default(),
+            payment_method: Input::default(),
+            premium: Input::new("0".to_string()),
+            sats_amount: Input::default(),
+            ln_address: Input::default(),
+            trading_mode: TradingMode::default(),
+            error: None,
+        }
+    }
+}
+
+impl OrderFormState {
+    /// Opens an empty form using `trading_mode` unless the user changes it
+    pub fn open(&mut self, trading_mode: TradingMode) {
+        *self = Self {
+            visible: true,
+            trading_mode,
+            ..Default::default()
+        };
+    }
+
+    fn input_mut(&mut self, field: OrderField) -> Option<&mut Input> {
+        match field {
+            OrderField::Kind | OrderField::TradingMode => None,
+            OrderField::FiatCode => Some(&mut self.fiat_code),
+            OrderField::FiatAmount => Some(&mut self.fiat_amount),
+            OrderField::PaymentMethod => Some(&mut self.payment_method),
+            OrderField::Premium => Some(&mut self.premium),
+            OrderField::SatsAmount => Some(&mut self.sats_amount),
+            OrderField::LnAddress => Some(&mut self.ln_address),
+        }
+    }
+
+    fn value(&self, field: OrderField) -> String {
+        match field {
+            OrderField::Kind => self.kind.to_string(),
+            OrderField::FiatCode => self.fiat_code.value().to_string(),
+            OrderField::FiatAmount => self.fiat_amount.value().to_string(),
+            OrderField::PaymentMethod => self.payment_method.value().to_string(),
+            OrderField::Premium => self.premium.value().to_string(),
+            OrderField::SatsAmount => self.sats_amount.value().to_string(),
+            OrderField::LnAddress => self.ln_address.value().to_string(),
+            OrderField::TradingMode => self.trading_mode.to_string(),
+        }
+    }
+
+    /// Handles a key while the form is open
+    ///
+    /// Returns the order to publish and its trading mode once the user
+    /// submits a valid form
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<(SmallOrder, TradingMode)> {
+        match key.code {
+            KeyCode::Esc => self.visible = false,
+            KeyCode::Tab | KeyCode::Down => self.focused = self.focused.next(self.kind),
+            KeyCode::BackTab | KeyCode::Up => self.focused = self.focused.previous(self.kind),
+            KeyCode::Char(' ') if self.focused == OrderField::Kind => {
+                self.kind = match self.kind {
+                    OrderKind::Buy => OrderKind::Sell,
+                    OrderKind::Sell => OrderKind::Buy,
+                }
+            }
+            KeyCode::Char(' ') if self.focused == OrderField::TradingMode => {
+                self.trading_mode = self.trading_mode.toggle()
+            }
+            KeyCode::Enter => match self.to_order() {
+                Ok(order) => {
+                    self.visible = false;
+                    return Some((order, self.trading_mode));
+                }
+                Err(e) => self.error = Some(e),
+            },
+            _ => {
+                if let Some(input) = self.input_mut(self.focused) {
+                    input.handle_event(&Event::Key(*key));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Validates the form and builds the order we send to Mostro
+    pub fn to_order(&self) -> Result<SmallOrder, String> {
+        let fiat_code = self.fiat_code.value().trim().to_uppercase();
+        if fiat_code.len() != 3 || !fiat_code.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err("The fiat code must have 3 letters, e.g. USD".to_string());
+        }
+
+        let fiat_amount = self.fiat_amount.value().trim();
+        let (fiat_amount, min_amount, max_amount) = match fiat_amount.split_once('-') {
+            Some((min, max)) => {
+                let min = parse_amount(min, "minimum fiat amount")?;
+                let max = parse_amount(max, "maximum fiat amount")?;
+                if min >= max {
+                    return Err("The minimum amount must be lower than the maximum".to_string());
+                }
+                (0, Some(min), Some(max))
+            }
+            None => (parse_amount(fiat_amount, "fiat amount")?, None, None),
+        };
+
+        let payment_method = self.payment_method.value().trim();
+        if payment_method.is_empty() {
+            return Err("The payment method can't be empty".to_string());
+        }
+
+        let premium = self
+            .premium
+            .value()
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| "The premium must be a whole number".to_string())?;
+
+        let sats_amount = self.sats_amount.value().trim();
+        let amount = if sats_amount.is_empty() {
+            0
+        } else {
+            parse_amount(sats_amount, "sats amount")?
+        };
+        if amount > 0 && min_amount.is_some() {
+            return Err("Range orders can't have a fixed sats amount".to_string());
+        }
+        if amount > 0 && premium != 0 {
+            return Err("Orders with a fixed sats amount can't have a premium".to_string());
+        }
+
+        let ln_address = self.ln_address.value().trim().to_lowercase();
+        let buyer_invoice = if self.kind == OrderKind::Sell || ln_address.is_empty() {
+            None
+        } else if is_valid_ln_address(&ln_address) || is_valid_lnurl(&ln_address) {
+            Some(ln_address)
+        } else {
+            return Err("Use a Lightning address like user@domain.com or an LNURL".to_string());
+        };
+
+        Ok(SmallOrder::new(
+            None,
+            Some(self.kind),
+            Some(Status::Pending),
+            amount,
+            fiat_code,
+            min_amount,
+            max_amount,
+            fiat_amount,
+            payment_method.to_string(),
+            premium,
+            None,
+            None,
+            buyer_invoice,
+            Some(0),
+            None,
+            None,
+            None,
+        ))
+    }
+}
+
+fn parse_amount(value: &str, name: &str) -> Result<i64, String> {
+    match value.trim().parse::<i64>() {
+        Ok(amount) if amount > 0 => Ok(amount),
+        _ => Err(format!("The {} must be a positive whole number", name)),
+    }
+}
+
+pub struct OrderFormWidget<'a> {
+    pub state: &'a OrderFormState,
+}
+
+impl<'a> OrderFormWidget<'a> {
+    pub fn new(state: &'a OrderFormState) -> Self {
+        Self { state }
+    }
+}
+
+impl Widget for OrderFormWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 50, 60);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let block = Block::bordered()
+            .title("New order")
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom("ESC to close, TAB to move, ENTER to publish");
+
+        let mut lines = vec![];
+        for field in OrderField::for_kind(self.state.kind) {
+            let label = format!("{:<16}", field.label());
+            let value = self.state.value(field);
+            if field == self.state.focused {
+                lines.push(Line::from(format!(">> {}{}", label, value)).white().bold());
+            } else {
+                lines.push(Line::raw(format!("   {}{}", label, value)));
+            }
+        }
+        lines.push(Line::raw(""));
+        lines.push(Line::raw(self.state.focused.hint()));
+        if let Some(error) = &self.state.error {
+            lines.push(Line::raw(""));
+            lines.push(Line::from(error.as_str()).red());
+        }
+
+        let paragraph = Paragraph::new(lines)
+            .block(block)
+            .cyan()
+            .wrap(Wrap { trim: false });
+        Clear.render(popup_area, buf);
+        paragraph.render(popup_area, buf);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::trade::{is_expiring, step_deadline};
+use crate::util::countdown;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::{EcLevel, QrCode};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Hold invoice Mostro asked us to pay as seller of an order
+#[derive(Debug, Clone)]
+pub struct PayInvoice {
+    pub order_id: Uuid,
+    pub invoice: String,
+    pub amount: i64,
+    /// Unix time after which Mostro cancels the order
+    pub deadline: i64,
+}
+
+impl PayInvoice {
+    /// `received_at` is when Mostro sent the invoice, `expires_at` the order
+    /// expiration if Mostro sent it
+    pub fn new(
+        order_id: Uuid,
+        invoice: String,
+        amount: i64,
+        received_at: i64,
+        expires_at: Option<i64>,
+    ) -> Self {
+        Self {
+            order_id,
+            invoice,
+            amount,
+            deadline: step_deadline(received_at, expires_at),
+        }
+    }
+}
+
+pub struct PayInvoiceWidget<'a> {
+    pub pay_invoice: &'a PayInvoice,
+}
+
+impl<'a> PayInvoiceWidget<'a> {
+    pub fn new(pay_invoice: &'a PayInvoice) -> Self {
+        Self { pay_invoice }
+    }
+}
+
+impl Widget for PayInvoiceWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 80, 90);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let block = Block::bordered()
+            .title("Pay hold invoice")
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom("ESC to close");
+        let inner_area = block.inner(popup_area);
+        Clear.render(popup_area, buf);
+        block.render(popup_area, buf);
+
+        let lines = vec![
+            Line::raw(format!(
+                "🧌 Pay this hold invoice of {} sats to start the trade of the order {}.",
+                self.pay_invoice.amount, self.pay_invoice.order_id
+            )),
+            Line::raw("The sats stay locked in your wallet until you release them or the trade is canceled."),
+            time_left_line(self.pay_invoice.deadline),
+            Line::raw(""),
+            Line::from(self.pay_invoice.invoice.as_str()).white(),
+        ];
+        let text_height: usize = lines
+            .iter()
+            .map(|l| l.width().max(1).div_ceil(inner_area.width.max(1) as usize))
+            .sum();
+        let text = Paragraph::new(lines).cyan().wrap(Wrap { trim: true });
+        let [text_area, qr_area] = Layout::vertical([
+            Constraint::Length(text_height as u16 + 1),
+            Constraint::Fill(1),
+        ])
+        .areas(inner_area);
+        text.render(text_area, buf);
+
+        // Uppercase invoices fit in a smaller QR code, wallets accept both
+        let code = match QrCode::with_error_correction_level(
+            self.pay_invoice.invoice.to_uppercase(),
+            EcLevel::L,
+        ) {
+            Ok(code) => code,
+            Err(e) => {
+                Line::from(format!("Error creating QR code: {}", e))
+                    .red()
+                    .render(qr_area, buf);
+                return;
+            }
+        };
+        let qr = code.render::<Dense1x2>().quiet_zone(true).build();
+        let qr_lines: Vec<&str> = qr.lines().collect();
+        let qr_width = qr_lines.first().map_or(0, |l| l.chars().count()) as u16;
+        if qr_width > qr_area.width || qr_lines.len() as u16 > qr_area.height {
+            Line::raw("Enlarge the terminal to see the QR code")
+                .centered()
+                .render(qr_area, buf);
+            return;
+        }
+        let x = qr_area.x + (qr_area.width - qr_width) / 2;
+        let style = Style::new().fg(Color::Black).bg(Color::White);
+        for (i, line) in qr_lines.iter().enumerate() {
+            buf.set_string(x, qr_area.y + i as u16, line, style);
+        }
+    }
+}
+
+/// Countdown to a deadline, in red when little time is left
+fn time_left_line(deadline: i64) -> Line<'static> {
+    let line = Line::from(format!("Time left: {}", countdown(deadline)));
+    if is_expiring(deadline) {
+        line.red().bold()
+    } else {
+        line
+    }
+}
@@ -0,0 +1,142 @@
+use crate::settings::{is_valid_profile_name, list_profiles, DEFAULT_PROFILE};
+use nostr_sdk::prelude::*;
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{Event, EventStream, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::palette::tailwind::BLUE,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, HighlightSpacing, List, ListState, Paragraph, StatefulWidget, Widget},
+    DefaultTerminal,
+};
+use std::str::FromStr;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+
+/// Lets the user pick the profile to use, or create a new one
+pub struct ProfileWidget {
+    profiles: Vec<String>,
+    list_state: ListState,
+    current: Option<String>,
+    creating: bool,
+    input: Input,
+    error: Option<String>,
+}
+
+impl ProfileWidget {
+    /// `current` is the profile in use, if any, ESC goes back to it
+    pub fn new(current: Option<String>) -> Self {
+        let mut profiles = vec![DEFAULT_PROFILE.to_string()];
+        profiles.extend(list_profiles());
+        let selected = current
+            .as_ref()
+            .and_then(|c| profiles.iter().position(|p| p == c))
+            .unwrap_or(0);
+
+        Self {
+            profiles,
+            list_state: ListState::default().with_selected(Some(selected)),
+            current,
+            creating: false,
+            input: Input::default(),
+            error: None,
+        }
+    }
+
+    /// Runs the picker until a profile is chosen
+    ///
+    /// Returns `None` if the user quits without choosing one
+    pub async fn run(mut self, terminal: &mut DefaultTerminal) -> Result<Option<String>> {
+        let mut events = EventStream::new();
+
+        loop {
+            terminal.draw(|frame| frame.render_widget(&mut self, frame.area()))?;
+            let Some(Ok(Event::Key(key))) = events.next().await else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if self.creating {
+                match key.code {
+                    KeyCode::Enter => {
+                        let name = self.input.value().trim().to_string();
+                        if is_valid_profile_name(&name) {
+                            return Ok(Some(name));
+                        }
+                        self.error = Some(
+                            "Use only letters, numbers, '-' and '_' in the profile name"
+                                .to_string(),
+                        );
+                    }
+                    KeyCode::Esc => {
+                        self.creating = false;
+                        self.error = None;
+                        self.input.reset();
+                    }
+                    _ => {
+                        self.input.handle_event(&Event::Key(key));
+                    }
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('j') | KeyCode::Down => self.list_state.select_next(),
+                KeyCode::Char('k') | KeyCode::Up => self.list_state.select_previous(),
+                KeyCode::Char('n') => self.creating = true,
+                KeyCode::Enter => {
+                    let selected = self.list_state.selected().unwrap_or(0);
+                    return Ok(self.profiles.get(selected).cloned());
+                }
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(self.current),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Widget for &mut ProfileWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 40, 50);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let hint = if self.creating {
+            "ESC to go back, ENTER to create"
+        } else {
+            "j/k to move, ENTER to select, n for a new profile"
+        };
+        let block = Block::bordered()
+            .title("Profiles")
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom(hint);
+        let inner_area = block.inner(popup_area);
+        Clear.render(popup_area, buf);
+        block.render(popup_area, buf);
+
+        let [list_area, input_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(3)]).areas(inner_area);
+        let items = self.profiles.iter().map(|p| {
+            if Some(p) == self.current.as_ref() {
+                format!("{} (current)", p)
+            } else {
+                p.clone()
+            }
+        });
+        let list = List::new(items)
+            .cyan()
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_symbol(">>")
+            .highlight_style(Style::default().fg(BLUE.c400));
+        StatefulWidget::render(list, list_area, buf, &mut self.list_state);
+
+        if self.creating {
+            let mut block = Block::bordered().title("New profile name");
+            if let Some(error) = &self.error {
+                block = block.title_bottom(Line::from(error.as_str()).red());
+            }
+            let input = Paragraph::new(Line::from(self.input.value()).white()).block(block);
+            input.render(input_area, buf);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{KeyCode, KeyEvent},
+    layout::Rect,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+};
+use std::str::FromStr;
+use uuid::Uuid;
+
+const MIN_RATING: u8 = 1;
+const MAX_RATING: u8 = 5;
+
+/// Rating of the counterparty Mostro asked us for
+#[derive(Debug, Clone)]
+pub struct RatingState {
+    pub order_id: Uuid,
+    pub rating: u8,
+}
+
+impl RatingState {
+    pub fn new(order_id: Uuid) -> Self {
+        Self {
+            order_id,
+            rating: MAX_RATING,
+        }
+    }
+
+    /// Returns the rating once the user sends it, `Some(None)` if the
+    /// user closed the popup without rating
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<Option<u8>> {
+        match key.code {
+            KeyCode::Char(c @ '1'..='5') => self.rating = c as u8 - b'0',
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.rating = self.rating.saturating_sub(1).max(MIN_RATING)
+            }
+            KeyCode::Right | KeyCode::Char('l') => self.rating = (self.rating + 1).min(MAX_RATING),
+            KeyCode::Enter => return Some(Some(self.rating)),
+            KeyCode::Esc => return Some(None),
+            _ => {}
+        }
+
+        None
+    }
+}
+
+impl Widget for &RatingState {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 50, 30);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let block = Block::bordered()
+            .title("Rate your counterparty")
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom("1-5 or h/l to choose, ENTER to send, ESC to rate later");
+        let stars = format!(
+            "{}{}",
+            "★ ".repeat(self.rating as usize),
+            "☆ ".repeat((MAX_RATING - self.rating) as usize)
+        );
+        let lines = vec![
+            Line::raw(format!(
+                "🧌 The trade of the order {} is over.",
+                self.order_id
+            )),
+            Line::raw("How did it go with your counterparty?"),
+            Line::raw(""),
+            Line::from(stars).yellow().bold().centered(),
+        ];
+        let paragraph = Paragraph::new(lines)
+            .block(block)
+            .cyan()
+            .wrap(Wrap { trim: true });
+        Clear.render(popup_area, buf);
+        paragraph.render(popup_area, buf);
+    }
+}
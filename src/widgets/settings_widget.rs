@@ -1,25 +1,120 @@
-use nostr_sdk::prelude::{PublicKey, SecretKey};
+use crate::identity::Identity;
+use crate::settings::Settings;
+use nostr_sdk::prelude::PublicKey;
 use nostr_sdk::ToBech32;
 use ratatui::{
     buffer::Buffer,
+    crossterm::event::{Event, KeyCode, KeyEvent},
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
-    widgets::{Block, Widget},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Padding, Paragraph, Widget, Wrap},
 };
 use std::str::FromStr;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
 
-pub struct SettingsWidget {
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum IdentityMode {
+    #[default]
+    Idle,
+    ConfirmReveal,
+    Reveal,
+    Import,
+    ConfirmGenerate,
+    ImportAdmin,
+}
+
+/// Changes to the identity requested from the settings tab
+#[derive(Debug)]
+pub enum IdentityCommand {
+    /// Import an nsec or a mnemonic
+    Import(String),
+    Generate,
+    /// Use this solver key in admin mode
+    AdminKey(String),
+}
+
+#[derive(Debug, Default)]
+pub struct IdentityState {
+    pub mode: IdentityMode,
+    pub input: Input,
+    pub error: Option<String>,
+}
+
+impl IdentityState {
+    /// True while the user is typing, so global shortcuts must be ignored
+    pub fn is_editing(&self) -> bool {
+        matches!(self.mode, IdentityMode::Import | IdentityMode::ImportAdmin)
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<IdentityCommand> {
+        match (&self.mode, key.code) {
+            (IdentityMode::Idle, KeyCode::Char('r')) => self.mode = IdentityMode::ConfirmReveal,
+            (IdentityMode::Idle, KeyCode::Char('i')) => {
+                self.input.reset();
+                self.error = None;
+                self.mode = IdentityMode::Import;
+            }
+            (IdentityMode::Idle, KeyCode::Char('g')) => self.mode = IdentityMode::ConfirmGenerate,
+            (IdentityMode::Idle, KeyCode::Char('a')) if Settings::get().admin_mode => {
+                self.input.reset();
+                self.error = None;
+                self.mode = IdentityMode::ImportAdmin;
+            }
+            (IdentityMode::ConfirmReveal, KeyCode::Char('y')) => self.mode = IdentityMode::Reveal,
+            (IdentityMode::ConfirmGenerate, KeyCode::Char('y')) => {
+                self.mode = IdentityMode::Idle;
+                return Some(IdentityCommand::Generate);
+            }
+            (IdentityMode::Import, KeyCode::Enter) => {
+                self.mode = IdentityMode::Idle;
+                let value = self.input.value().trim().to_string();
+                self.input.reset();
+                return Some(IdentityCommand::Import(value));
+            }
+            (IdentityMode::ImportAdmin, KeyCode::Enter) => {
+                self.mode = IdentityMode::Idle;
+                let value = self.input.value().trim().to_string();
+                self.input.reset();
+                return Some(IdentityCommand::AdminKey(value));
+            }
+            (IdentityMode::Import | IdentityMode::ImportAdmin, KeyCode::Esc) => {
+                self.input.reset();
+                self.mode = IdentityMode::Idle;
+            }
+            (IdentityMode::Import | IdentityMode::ImportAdmin, _) => {
+                self.input.handle_event(&Event::Key(*key));
+            }
+            (
+                IdentityMode::ConfirmReveal | IdentityMode::ConfirmGenerate,
+                KeyCode::Char('n') | KeyCode::Esc,
+            )
+            | (IdentityMode::Reveal, KeyCode::Esc) => self.mode = IdentityMode::Idle,
+            _ => {}
+        }
+
+        None
+    }
+}
+
+pub struct SettingsWidget<'a> {
     pub pubkey: PublicKey,
-    pub secret: SecretKey,
+    pub identity: &'a Identity,
+    pub state: &'a IdentityState,
 }
 
-impl SettingsWidget {
-    pub fn new(pubkey: PublicKey, secret: SecretKey) -> Self {
-        Self { pubkey, secret }
+impl<'a> SettingsWidget<'a> {
+    pub fn new(pubkey: PublicKey, identity: &'a Identity, state: &'a IdentityState) -> Self {
+        Self {
+            pubkey,
+            identity,
+            state,
+        }
     }
 }
 
-impl Widget for SettingsWidget {
+impl Widget for SettingsWidget<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let layout = create_layout(area);
         render_block(
@@ -29,13 +124,111 @@ impl Widget for SettingsWidget {
             "Public key of this mostro operator",
             &self.pubkey.to_bech32().unwrap(),
         );
-        render_block(
-            layout[1],
-            buf,
-            "Secret key 🔑",
-            "Be mindful of this information",
-            &self.secret.to_bech32().unwrap(),
-        );
+        self.render_identity(layout[1], buf);
+
+        let popup = match self.state.mode {
+            IdentityMode::ConfirmReveal => Some((
+                "Reveal secrets",
+                "y to reveal, n to cancel",
+                vec![
+                    Line::raw("Your nsec and mnemonic will be shown on screen."),
+                    Line::raw("Make sure nobody is looking at it."),
+                ],
+            )),
+            IdentityMode::ConfirmGenerate => Some((
+                "Generate identity",
+                "y to generate, n to cancel",
+                vec![
+                    Line::raw("A new mnemonic will replace the current one in the keystore."),
+                    Line::raw("Trades started with the current mnemonic can only be recovered with it, make sure you have a backup."),
+                    Line::raw("Finish your open trades first, the new mnemonic can't take them over."),
+                ],
+            )),
+            IdentityMode::Import => Some((
+                "Import identity",
+                "ESC to cancel, ENTER to import",
+                vec![
+                    Line::raw("Paste an nsec to use it as identity key, or a mnemonic to replace the current one:"),
+                    Line::raw(""),
+                    Line::from(self.state.input.value()).white(),
+                ],
+            )),
+            IdentityMode::ImportAdmin => Some((
+                "Solver key",
+                "ESC to cancel, ENTER to save",
+                vec![
+                    Line::raw("Paste your solver private key, hex or nsec, it is saved encrypted in the keystore:"),
+                    Line::raw(""),
+                    Line::from("*".repeat(self.state.input.value().chars().count())).white(),
+                ],
+            )),
+            _ => None,
+        };
+        if let Some((title, hint, lines)) = popup {
+            let popup_area = crate::popup_area(area, 60, 40);
+            let color: Color = Color::from_str("#14161C").unwrap();
+            let block = Block::bordered()
+                .title(title)
+                .bg(color)
+                .title_style(Style::new().fg(Color::White))
+                .title_bottom(hint);
+            let paragraph = Paragraph::new(lines)
+                .block(block)
+                .cyan()
+                .wrap(Wrap { trim: true });
+            Clear.render(popup_area, buf);
+            paragraph.render(popup_area, buf);
+        }
+    }
+}
+
+impl SettingsWidget<'_> {
+    fn render_identity(&self, area: Rect, buf: &mut Buffer) {
+        let label_color = Style::default().fg(Color::from_str("#14161C").unwrap());
+        let value_color = Style::default().fg(Color::White);
+        let keys = self.identity.identity_keys();
+        let npub = match &keys {
+            Ok(keys) => keys.public_key().to_bech32().unwrap(),
+            Err(e) => e.to_string(),
+        };
+        let mut lines = vec![
+            Line::styled("Public key of your identity", label_color),
+            Line::raw(""),
+            Line::styled(npub, value_color),
+            Line::raw(""),
+        ];
+        if self.state.mode == IdentityMode::Reveal {
+            if let Ok(keys) = &keys {
+                lines.push(Line::styled("Secret key", label_color));
+                lines.push(Line::styled(
+                    keys.secret_key().to_bech32().unwrap(),
+                    value_color,
+                ));
+                lines.push(Line::raw(""));
+            }
+            lines.push(Line::styled("Mnemonic", label_color));
+            lines.push(Line::styled(self.identity.mnemonic(), value_color));
+        } else {
+            lines.push(Line::styled("Secret key", label_color));
+            lines.push(Line::styled("*".repeat(63), value_color));
+        }
+        if let Some(error) = &self.state.error {
+            lines.push(Line::raw(""));
+            lines.push(Line::from(error.as_str()).red());
+        }
+        let hint = if self.state.mode == IdentityMode::Reveal {
+            "ESC to hide secrets"
+        } else if Settings::get().admin_mode {
+            "r to reveal secrets, i to import, g to generate a new identity, a to set the solver key, s to restore trades, p to switch profile"
+        } else {
+            "r to reveal secrets, i to import, g to generate a new identity, s to restore trades, p to switch profile"
+        };
+        let block = Block::bordered()
+            .title("Identity 🔑")
+            .title_bottom(hint)
+            .padding(Padding::new(2, 2, 1, 0));
+        let paragraph = Paragraph::new(lines).block(block).wrap(Wrap { trim: true });
+        paragraph.render(area, buf);
     }
 }
 
@@ -0,0 +1,242 @@
+use crate::identity::Identity;
+use crate::keystore::Keystore;
+use nostr_sdk::prelude::*;
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{Event, EventStream, KeyCode, KeyEventKind},
+    layout::Rect,
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, Paragraph, Widget, Wrap},
+    DefaultTerminal,
+};
+use std::str::FromStr;
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+
+enum SetupStep {
+    Unlock,
+    Choose,
+    Import,
+    Backup(Identity),
+    NewPassphrase(Identity),
+    ConfirmPassphrase(Identity, String),
+}
+
+/// How the user left the setup screen
+pub enum SetupOutcome {
+    Unlocked(Identity, Keystore),
+    /// The user wants to pick another profile
+    Back,
+    Quit,
+}
+
+/// Startup screen, unlocks the keystore or lets the user generate or
+/// import a mnemonic the first time mostrui runs
+pub struct SetupWidget {
+    step: SetupStep,
+    input: Input,
+    error: Option<String>,
+    busy: bool,
+}
+
+impl SetupWidget {
+    pub fn new() -> Self {
+        let step = if Keystore::exists() {
+            SetupStep::Unlock
+        } else {
+            SetupStep::Choose
+        };
+
+        Self {
+            step,
+            input: Input::default(),
+            error: None,
+            busy: false,
+        }
+    }
+
+    /// Runs the setup screen until the user has an unlocked identity,
+    /// goes back to the profile picker or quits
+    pub async fn run(mut self, terminal: &mut DefaultTerminal) -> Result<SetupOutcome> {
+        let mut events = EventStream::new();
+
+        loop {
+            terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
+            let Some(Ok(Event::Key(key))) = events.next().await else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code == KeyCode::Enter
+                && matches!(
+                    self.step,
+                    SetupStep::Unlock | SetupStep::ConfirmPassphrase(..)
+                )
+            {
+                // Deriving the keystore key takes a while, let the user know
+                self.busy = true;
+                terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
+                self.busy = false;
+            }
+            let step = std::mem::replace(&mut self.step, SetupStep::Choose);
+            self.step = match (step, key.code) {
+                (SetupStep::Unlock, KeyCode::Enter) => {
+                    let keystore = Keystore::new(self.input.value());
+                    match keystore.load() {
+                        Ok(identity) => return Ok(SetupOutcome::Unlocked(identity, keystore)),
+                        Err(e) => {
+                            self.input.reset();
+                            self.error = Some(format!("Could not unlock keystore: {}", e));
+                            SetupStep::Unlock
+                        }
+                    }
+                }
+                (SetupStep::Unlock, KeyCode::Esc) => return Ok(SetupOutcome::Quit),
+                (SetupStep::Choose, KeyCode::Char('g')) => SetupStep::Backup(Identity::generate()?),
+                (SetupStep::Choose, KeyCode::Char('i')) => SetupStep::Import,
+                (SetupStep::Choose, KeyCode::Char('q')) => return Ok(SetupOutcome::Quit),
+                (SetupStep::Choose, KeyCode::Esc) => return Ok(SetupOutcome::Back),
+                (SetupStep::Import, KeyCode::Enter) => {
+                    match Identity::from_mnemonic(self.input.value()) {
+                        Ok(identity) => {
+                            self.input.reset();
+                            self.error = None;
+                            SetupStep::NewPassphrase(identity)
+                        }
+                        Err(e) => {
+                            self.error = Some(format!("Invalid mnemonic: {}", e));
+                            SetupStep::Import
+                        }
+                    }
+                }
+                (SetupStep::Import, KeyCode::Esc) => {
+                    self.input.reset();
+                    self.error = None;
+                    SetupStep::Choose
+                }
+                (SetupStep::Backup(identity), KeyCode::Enter) => SetupStep::NewPassphrase(identity),
+                (SetupStep::Backup(_), KeyCode::Esc) => SetupStep::Choose,
+                (SetupStep::NewPassphrase(identity), KeyCode::Enter) => {
+                    if self.input.value().is_empty() {
+                        self.error = Some("The passphrase can't be empty".to_string());
+                        SetupStep::NewPassphrase(identity)
+                    } else {
+                        let passphrase = self.input.value().to_string();
+                        self.input.reset();
+                        self.error = None;
+                        SetupStep::ConfirmPassphrase(identity, passphrase)
+                    }
+                }
+                (SetupStep::ConfirmPassphrase(identity, passphrase), KeyCode::Enter) => {
+                    let confirmed = self.input.value() == passphrase;
+                    self.input.reset();
+                    if !confirmed {
+                        self.error = Some("Passphrases don't match, try again".to_string());
+                        SetupStep::NewPassphrase(identity)
+                    } else {
+                        let keystore = Keystore::new(&passphrase);
+                        keystore.save(&identity)?;
+                        return Ok(SetupOutcome::Unlocked(identity, keystore));
+                    }
+                }
+                (SetupStep::NewPassphrase(_) | SetupStep::ConfirmPassphrase(..), KeyCode::Esc) => {
+                    self.input.reset();
+                    self.error = None;
+                    SetupStep::Choose
+                }
+                (
+                    step @ (SetupStep::Unlock
+                    | SetupStep::Import
+                    | SetupStep::NewPassphrase(_)
+                    | SetupStep::ConfirmPassphrase(..)),
+                    _,
+                ) => {
+                    self.input.handle_event(&Event::Key(key));
+                    step
+                }
+                (step, _) => step,
+            };
+        }
+    }
+}
+
+impl Widget for &SetupWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 60, 40);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let masked = "*".repeat(self.input.value().chars().count());
+        let (hint, mut lines) = match &self.step {
+            SetupStep::Unlock => (
+                "ESC to quit, ENTER to unlock",
+                vec![
+                    Line::raw("🧌 Enter the passphrase of your keystore:"),
+                    Line::raw(""),
+                    Line::from(masked).white(),
+                ],
+            ),
+            SetupStep::Choose => (
+                "g to generate, i to import, ESC for profiles, q to quit",
+                vec![
+                    Line::raw("🧌 No identity was found in this computer."),
+                    Line::raw(""),
+                    Line::raw("You can generate a new mnemonic or import one you already have."),
+                ],
+            ),
+            SetupStep::Import => (
+                "ESC to go back, ENTER to import",
+                vec![
+                    Line::raw("Type your 12 or 24 words mnemonic separated by spaces:"),
+                    Line::raw(""),
+                    Line::from(self.input.value()).white(),
+                ],
+            ),
+            SetupStep::Backup(identity) => (
+                "ESC to go back, ENTER to continue",
+                vec![
+                    Line::raw("Write down these words and keep them in a safe place,"),
+                    Line::raw("they are the only way to recover your identity and trades:"),
+                    Line::raw(""),
+                    Line::from(identity.mnemonic()).white().bold(),
+                ],
+            ),
+            SetupStep::NewPassphrase(_) => (
+                "ESC to go back, ENTER to continue",
+                vec![
+                    Line::raw("Choose a passphrase to encrypt your keystore,"),
+                    Line::raw("you will need it every time you start mostrui:"),
+                    Line::raw(""),
+                    Line::from(masked).white(),
+                ],
+            ),
+            SetupStep::ConfirmPassphrase(..) => (
+                "ESC to go back, ENTER to save",
+                vec![
+                    Line::raw("Type the passphrase again:"),
+                    Line::raw(""),
+                    Line::from(masked).white(),
+                ],
+            ),
+        };
+        if self.busy {
+            lines.push(Line::raw(""));
+            lines.push(Line::raw("Working..."));
+        } else if let Some(error) = &self.error {
+            lines.push(Line::raw(""));
+            lines.push(Line::from(error.as_str()).red());
+        }
+        let block = Block::bordered()
+            .title("Identity setup")
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom(hint);
+        let paragraph = Paragraph::new(lines)
+            .block(block)
+            .cyan()
+            .wrap(Wrap { trim: true });
+
+        Clear.render(popup_area, buf);
+        paragraph.render(popup_area, buf);
+    }
+}
@@ -0,0 +1,716 @@
+use crate::db::{Order, TradeKey, Transition};
+use crate::trade::{
+    action_name, has_deadline, is_expiring, is_finished, parse_action, Role, Trade,
+};
+use crate::util::countdown;
+use chrono::{Local, TimeZone};
+use mostro_core::message::{Action, Content};
+use mostro_core::order::Status;
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    layout::{Constraint, Layout, Rect},
+    style::palette::tailwind::{BLUE, SLATE},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{
+        Block, Cell, Clear, HighlightSpacing, Paragraph, Row, StatefulWidget, Table, TableState,
+        Widget, Wrap,
+    },
+};
+use sqlx::SqlitePool;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tui_input::backend::crossterm::EventHandler;
+use tui_input::Input;
+use uuid::Uuid;
+
+/// Status of the last trade restore started from the mnemonic
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RestoreState {
+    #[default]
+    Idle,
+    Restoring,
+    Restored(usize),
+    Failed(String),
+}
+
+#[derive(Debug, Default)]
+pub struct TradesState {
+    pub orders: Vec<Order>,
+    pub trade_keys: Vec<TradeKey>,
+    /// Messages Mostro sent about our trades, oldest first
+    pub transitions: Vec<Transition>,
+    pub restore: RestoreState,
+    /// Last request on each order that Mostro refused or didn't answer
+    pub errors: Vec<(Uuid, String)>,
+    /// Show only the orders we published that nobody took yet
+    pub pending_only: bool,
+    pub table_state: TableState,
+}
+
+impl TradesState {
+    fn visible_orders(&self) -> Vec<&Order> {
+        self.orders
+            .iter()
+            .filter(|order| !self.pending_only || self.is_own_pending(order))
+            .collect()
+    }
+
+    fn is_own_pending(&self, order: &Order) -> bool {
+        let is_maker = self
+            .trade_keys
+            .iter()
+            .any(|k| k.order_id == order.id && k.role == "maker");
+        is_maker && order.status.as_deref() == Some("pending")
+    }
+
+    fn transitions_of<'a>(&'a self, order: &'a Order) -> impl Iterator<Item = &'a Transition> {
+        self.transitions
+            .iter()
+            .filter(move |t| Some(&t.order_id) == order.id.as_ref())
+    }
+
+    /// Last message Mostro sent about the trade that we applied
+    fn last_action(&self, order: &Order) -> Option<String> {
+        let transition = self.transitions_of(order).filter(|t| t.accepted).last()?;
+        parse_action(&transition.action).map(|a| a.to_string())
+    }
+
+    /// True if the counterparty asked for a cooperative cancel of the open trade
+    ///
+    /// The request is saved as a transition, so it survives a restart.
+    fn cancel_requested(&self, order: &Order) -> bool {
+        let open = order
+            .status
+            .as_deref()
+            .and_then(|status| Status::from_str(status).ok())
+            .is_some_and(|status| !is_finished(status));
+        let asked = action_name(&Action::CooperativeCancelInitiatedByPeer);
+        open && self
+            .transitions_of(order)
+            .any(|t| t.accepted && t.action == asked)
+    }
+
+    /// When the trade got its current status
+    fn status_since(&self, order: &Order) -> Option<i64> {
+        self.transitions_of(order)
+            .filter(|t| t.accepted && t.from_status != t.to_status)
+            .last()
+            .map(|t| t.created_at)
+            .or(order.created_at)
+    }
+}
+
+/// Public key of the other side of the trade, once Mostro told us
+pub fn counterparty(order: &Order, trade_key: Option<&TradeKey>) -> Option<String> {
+    match trade_key.map(|k| Role::of(order, k)) {
+        Some(Role::Buyer) => order.master_seller_pubkey.clone(),
+        Some(Role::Seller) => order.master_buyer_pubkey.clone(),
+        None => None,
+    }
+}
+
+/// Short form of a long id or public key
+fn short(value: &str) -> String {
+    match value.char_indices().nth(8) {
+        Some((i, _)) => format!("{}…", &value[..i]),
+        None => value.to_string(),
+    }
+}
+
+/// Time since `since` in its two largest units, e.g. `2h 5m`
+fn elapsed(since: i64) -> String {
+    let secs = (chrono::Utc::now().timestamp() - since).max(0);
+    let (days, hours, mins) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h),
+    }
+}
+
+/// Orders we are trading, read from the local database
+#[derive(Debug, Clone, Default)]
+pub struct TradesWidget {
+    pub state: Arc<RwLock<TradesState>>,
+}
+
+impl TradesWidget {
+    /// Reads our orders again from the database
+    pub async fn reload(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let orders = Order::all(pool).await?;
+        let trade_keys = TradeKey::all(pool).await?;
+        let transitions = Transition::all(pool).await?;
+        let mut state = self.state.write().unwrap();
+        state.orders = orders;
+        state.trade_keys = trade_keys;
+        state.transitions = transitions;
+        if state.table_state.selected().is_none() && !state.orders.is_empty() {
+            state.table_state.select(Some(0));
+        }
+
+        Ok(())
+    }
+
+    /// Selected order and the trade key we use in it
+    pub fn selected(&self) -> Option<(Order, TradeKey)> {
+        let state = self.state.read().unwrap();
+        let orders = state.visible_orders();
+        let order = *orders.get(state.table_state.selected()?)?;
+        let trade_key = state.trade_keys.iter().find(|k| k.order_id == order.id)?;
+
+        Some((order.clone(), trade_key.clone()))
+    }
+
+    pub fn toggle_pending_only(&self) {
+        let mut state = self.state.write().unwrap();
+        state.pending_only = !state.pending_only;
+        state.table_state.select(Some(0));
+    }
+
+    pub fn has_cancel_request(&self, order_id: Uuid) -> bool {
+        let state = self.state.read().unwrap();
+        let id = order_id.to_string();
+        state
+            .orders
+            .iter()
+            .find(|order| order.id.as_ref() == Some(&id))
+            .is_some_and(|order| state.cancel_requested(order))
+    }
+
+    pub fn set_error(&self, order_id: Uuid, error: String) {
+        let mut state = self.state.write().unwrap();
+        state.errors.retain(|(id, _)| *id != order_id);
+        state.errors.push((order_id, error));
+    }
+
+    pub fn clear_error(&self, order_id: Uuid) {
+        self.state
+            .write()
+            .unwrap()
+            .errors
+            .retain(|(id, _)| *id != order_id);
+    }
+
+    pub fn set_restore_state(&self, restore: RestoreState) {
+        self.state.write().unwrap().restore = restore;
+    }
+
+    pub fn scroll_down(&self) {
+        self.state.write().unwrap().table_state.scroll_down_by(1);
+    }
+
+    pub fn scroll_up(&self) {
+        self.state.write().unwrap().table_state.scroll_up_by(1);
+    }
+}
+
+impl Widget for &TradesWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [table_area, details_area] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(12)]).areas(area);
+        let mut state = self.state.write().unwrap();
+
+        let restore = match &state.restore {
+            RestoreState::Idle => Line::raw(""),
+            RestoreState::Restoring => Line::raw("Restoring trades..."),
+            RestoreState::Restored(count) => Line::raw(format!("Restored {} trades", count)),
+            RestoreState::Failed(e) => Line::from(format!("Restore failed: {}", e)).red(),
+        };
+        let color: Color = Color::from_str("#1D212C").unwrap();
+        let title = if state.pending_only {
+            " My Trades · pending orders "
+        } else {
+            " My Trades "
+        };
+        // Only offer the commands the selected trade allows right now
+        let mut hint = vec!["j/k to scroll", "o to show only pending orders"];
+        let selected = state
+            .table_state
+            .selected()
+            .and_then(|i| state.visible_orders().get(i).copied());
+        let trade_key =
+            selected.and_then(|order| state.trade_keys.iter().find(|k| k.order_id == order.id));
+        let trade = selected
+            .zip(trade_key)
+            .and_then(|(order, trade_key)| Trade::new(order, trade_key).ok());
+        let allowed_actions = trade
+            .as_ref()
+            .map(|t| t.allowed_actions())
+            .unwrap_or_default();
+        for action in &allowed_actions {
+            match action {
+                Action::AddInvoice => hint.push("i to add your invoice"),
+                Action::FiatSent => hint.push("f to mark fiat sent"),
+                Action::Release => hint.push("r to release"),
+                Action::Cancel => hint.push("c to cancel"),
+                Action::Dispute => hint.push("d to open a dispute"),
+                _ => {}
+            }
+        }
+        if selected.is_some_and(|order| counterparty(order, trade_key).is_some()) {
+            hint.push("m to chat");
+        }
+        hint.push("q to quit");
+        let block = Block::bordered()
+            .title(title)
+            .title(restore.right_aligned())
+            .bg(color)
+            .title_bottom(hint.join(", "));
+
+        let rows = state.visible_orders().into_iter().map(|order| {
+            let trade_key = state.trade_keys.iter().find(|k| k.order_id == order.id);
+            let role = match trade_key.map(|k| Role::of(order, k)) {
+                Some(Role::Buyer) => "buyer",
+                Some(Role::Seller) => "seller",
+                None => "",
+            };
+            let expires = match deadline(order) {
+                Some(deadline) if is_expiring(deadline) => Cell::from(countdown(deadline)).red(),
+                Some(deadline) => Cell::from(countdown(deadline)),
+                None => Cell::from(""),
+            };
+            Row::new(vec![
+                Cell::from(role),
+                Cell::from(status_text(&state, order, trade_key)),
+                Cell::from(order.fiat_code.clone()),
+                Cell::from(order.amount.to_string()),
+                Cell::from(fiat_amount(order)),
+                Cell::from(
+                    counterparty(order, trade_key)
+                        .map(|p| short(&p))
+                        .unwrap_or_default(),
+                ),
+                Cell::from(state.last_action(order).unwrap_or_default()),
+                Cell::from(state.status_since(order).map(elapsed).unwrap_or_default()),
+                expires,
+                Cell::from(short(order.id.as_deref().unwrap_or_default())),
+            ])
+        });
+        let widths = [
+            Constraint::Length(6),
+            Constraint::Length(24),
+            Constraint::Length(4),
+            Constraint::Length(10),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(10),
+        ];
+        let color = Color::from_str("#304F00").unwrap();
+        let header_style = Style::default().fg(SLATE.c200).bg(color);
+        let selected_style = Style::default().fg(BLUE.c400);
+        let header = [
+            "Role",
+            "Status",
+            "Code",
+            "Amount",
+            "Fiat Amount",
+            "Counterparty",
+            "Last Action",
+            "In State",
+            "Expires",
+            "Id",
+        ]
+        .into_iter()
+        .map(Cell::from)
+        .collect::<Row>()
+        .style(header_style)
+        .height(1);
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(block)
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_symbol(">>")
+            .row_highlight_style(selected_style);
+
+        let lines = match selected.cloned() {
+            Some(order) => {
+                let side = match trade_key {
+                    Some(k) => format!("{} as {}", order.kind.clone().unwrap_or_default(), k.role),
+                    None => order.kind.clone().unwrap_or_default(),
+                };
+                let mut lines = vec![
+                    Line::raw(format!(
+                        "Order: {}  ({})",
+                        order.id.clone().unwrap_or_default(),
+                        side
+                    )),
+                    Line::raw(format!(
+                        "{} sats for {} {} by {}, premium {}%",
+                        order.amount,
+                        fiat_amount(&order),
+                        order.fiat_code,
+                        order.payment_method,
+                        order.premium
+                    )),
+                    Line::raw(format!(
+                        "Counterparty: {}",
+                        counterparty(&order, trade_key)
+                            .unwrap_or_else(|| "not known yet".to_string())
+                    )),
+                    Line::raw(format!(
+                        "Status: {}, for {}",
+                        status_text(&state, &order, trade_key),
+                        state.status_since(&order).map(elapsed).unwrap_or_default()
+                    )),
+                ];
+                if let Some(deadline) = deadline(&order) {
+                    let line = Line::from(format!("Expires in {}", countdown(deadline)));
+                    lines.push(if is_expiring(deadline) {
+                        line.red().bold()
+                    } else {
+                        line
+                    });
+                }
+                let error = order
+                    .id
+                    .as_deref()
+                    .and_then(|id| Uuid::parse_str(id).ok())
+                    .and_then(|id| state.errors.iter().find(|(e, _)| *e == id));
+                if let Some((_, error)) = error {
+                    lines.push(Line::from(error.clone()).red());
+                }
+                let actions: Vec<String> = allowed_actions.iter().map(|a| a.to_string()).collect();
+                lines.push(Line::raw(format!(
+                    "You can: {}",
+                    if actions.is_empty() {
+                        "nothing, waiting for Mostro or the counterparty".to_string()
+                    } else {
+                        actions.join(", ")
+                    }
+                )));
+                lines.push(Line::raw("Last messages:"));
+                let transitions: Vec<&Transition> = state.transitions_of(&order).collect();
+                for transition in transitions.iter().rev().take(4) {
+                    let action = parse_action(&transition.action)
+                        .map_or(transition.action.clone(), |a| a.to_string());
+                    let time = Local
+                        .timestamp_opt(transition.created_at, 0)
+                        .single()
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default();
+                    let line = if transition.accepted {
+                        Line::raw(format!("  {} {} → {}", time, action, transition.to_status))
+                    } else {
+                        Line::from(format!(
+                            "  {} {} ignored while {}",
+                            time, action, transition.from_status
+                        ))
+                        .red()
+                    };
+                    lines.push(line);
+                }
+                lines
+            }
+            None => vec![Line::raw("Select a trade to see its details")],
+        };
+        let details = Paragraph::new(lines)
+            .block(Block::bordered().title(" Trade details "))
+            .wrap(Wrap { trim: true });
+
+        StatefulWidget::render(table, table_area, buf, &mut state.table_state);
+        details.render(details_area, buf);
+    }
+}
+
+/// When the current step of the trade ends if nobody acts
+fn deadline(order: &Order) -> Option<i64> {
+    let status = Status::from_str(order.status.as_deref()?).ok()?;
+    if has_deadline(status) {
+        order.expires_at
+    } else {
+        None
+    }
+}
+
+fn fiat_amount(order: &Order) -> String {
+    match (order.min_amount, order.max_amount) {
+        (Some(min), Some(max)) if order.fiat_amount == 0 => format!("{}-{}", min, max),
+        _ => order.fiat_amount.to_string(),
+    }
+}
+
+/// Status of the trade with what the user should know about it
+fn status_text(state: &TradesState, order: &Order, trade_key: Option<&TradeKey>) -> String {
+    let cancel_requested = state.cancel_requested(order);
+    let token = match trade_key.map(|k| Role::of(order, k)) {
+        Some(Role::Buyer) => order.buyer_token,
+        Some(Role::Seller) => order.seller_token,
+        None => None,
+    };
+    match (cancel_requested, token) {
+        (true, _) => "cancel requested".to_string(),
+        (false, Some(token)) if order.status.as_deref() == Some("dispute") => {
+            format!("dispute, token {}", token)
+        }
+        _ => order.status.clone().unwrap_or_default(),
+    }
+}
+
+/// Command on one of our trades waiting for the user to confirm it
+///
+/// Once sent, the popup stays open showing Mostro's replies for the order.
+#[derive(Debug)]
+pub struct TradeConfirm {
+    pub order_id: Uuid,
+    pub action: Action,
+    /// Characters the user must type to confirm commands that move funds
+    code: Option<String>,
+    input: Input,
+    error: Option<String>,
+    cancel: CancelMode,
+    pub sent: bool,
+    pub replies: Vec<String>,
+}
+
+/// Why we are canceling an order, the popup explains what will happen
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CancelMode {
+    /// Ask the counterparty to cancel a trade that already started
+    #[default]
+    Cooperative,
+    /// The counterparty asked for a cooperative cancel, confirming accepts it
+    PeerAsked,
+    /// Take down an order we published that nobody took yet
+    Pending,
+    /// Cancel a taken order that is still waiting for the invoices, Mostro
+    /// cancels it without asking the counterparty
+    Unilateral,
+}
+
+/// What the user did in the confirmation popup
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfirmOutcome {
+    Confirmed,
+    Closed,
+}
+
+impl TradeConfirm {
+    pub fn new(order_id: Uuid, action: Action) -> Self {
+        let code = match action {
+            Action::Release | Action::AdminSettle | Action::AdminCancel => {
+                let id = order_id.simple().to_string();
+                Some(id[id.len() - 6..].to_string())
+            }
+            _ => None,
+        };
+
+        Self {
+            order_id,
+            action,
+            code,
+            input: Input::default(),
+            error: None,
+            cancel: CancelMode::default(),
+            sent: false,
+            replies: vec![],
+        }
+    }
+
+    pub fn cancel(order_id: Uuid, cancel: CancelMode) -> Self {
+        Self {
+            cancel,
+            ..Self::new(order_id, Action::Cancel)
+        }
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Option<ConfirmOutcome> {
+        if self.sent {
+            return match key.code {
+                KeyCode::Esc | KeyCode::Enter => Some(ConfirmOutcome::Closed),
+                _ => None,
+            };
+        }
+        match (&self.code, key.code) {
+            (_, KeyCode::Esc) => return Some(ConfirmOutcome::Closed),
+            (Some(code), KeyCode::Enter) => {
+                if self.input.value().trim() == code {
+                    return Some(ConfirmOutcome::Confirmed);
+                }
+                self.input.reset();
+                self.error = Some("The characters don't match, try again".to_string());
+            }
+            (Some(_), _) => {
+                self.input.handle_event(&Event::Key(*key));
+            }
+            (None, KeyCode::Char('y')) => return Some(ConfirmOutcome::Confirmed),
+            (None, KeyCode::Char('i')) if self.cancel == CancelMode::PeerAsked => {
+                return Some(ConfirmOutcome::Closed)
+            }
+            (None, KeyCode::Char('n')) => return Some(ConfirmOutcome::Closed),
+            _ => {}
+        }
+
+        None
+    }
+}
+
+impl Widget for &TradeConfirm {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let popup_area = crate::popup_area(area, 50, 40);
+        let color: Color = Color::from_str("#14161C").unwrap();
+        let (title, text) = match self.action {
+            Action::FiatSent => (
+                "Fiat sent",
+                "Tell the seller you already sent the fiat amount? Do it only after the payment left your account.",
+            ),
+            Action::Release => (
+                "Release",
+                "Release the sats to the buyer? Do it only after the fiat arrived to your account, this can't be undone.",
+            ),
+            Action::Cancel => match self.cancel {
+                CancelMode::Cooperative => (
+                    "Cancel",
+                    "Ask to cancel this trade? Your counterparty has to agree to cancel it.",
+                ),
+                CancelMode::PeerAsked => (
+                    "Cancel requested",
+                    "Your counterparty asked to cancel this trade, if you accept it the sats go back to the seller.",
+                ),
+                CancelMode::Pending => (
+                    "Cancel order",
+                    "Take down this order? Nobody took it yet, it will be removed from the order book.",
+                ),
+                CancelMode::Unilateral => (
+                    "Cancel",
+                    "Cancel this trade? No sats or fiat moved yet, Mostro cancels it right away without asking your counterparty.",
+                ),
+            },
+            Action::Dispute => (
+                "Open dispute",
+                "Open a dispute? A solver will contact both parties and decide who gets the sats, keep your dispute token at hand.",
+            ),
+            Action::AdminTakeDispute => (
+                "Take dispute",
+                "Take this dispute? You will see the order details and both parties' tokens.",
+            ),
+            Action::AdminSettle => (
+                "Settle",
+                "Settle the order? The hold invoice is charged and the buyer gets the sats.",
+            ),
+            Action::AdminCancel => (
+                "Cancel order",
+                "Cancel the order? The hold invoice is canceled and the sats go back to the seller.",
+            ),
+            _ => ("Confirm", "Send this command to Mostro?"),
+        };
+        let hint = match (&self.code, self.sent) {
+            (_, true) => "ESC to close",
+            (None, false) if self.cancel == CancelMode::PeerAsked => "y to accept, i to ignore",
+            (Some(_), false) => "ESC to cancel, ENTER to confirm",
+            (None, false) => "y to confirm, n to cancel",
+        };
+        let block = Block::bordered()
+            .title(title)
+            .bg(color)
+            .title_style(Style::new().fg(Color::White))
+            .title_bottom(hint);
+        let mut lines = vec![
+            Line::raw(text),
+            Line::raw(""),
+            Line::raw(format!("Order: {}", self.order_id)),
+        ];
+        if self.sent {
+            lines.push(Line::raw(""));
+            lines.push(Line::raw(format!(
+                "{} sent, waiting for Mostro...",
+                self.action
+            )));
+            for reply in &self.replies {
+                lines.push(Line::from(reply.as_str()).white());
+            }
+        } else if let Some(code) = &self.code {
+            lines.push(Line::raw(""));
+            lines.push(Line::raw(format!(
+                "Type the last characters of the order id ({}) to confirm:",
+                code
+            )));
+            lines.push(Line::from(self.input.value()).white());
+        }
+        if let Some(error) = &self.error {
+            lines.push(Line::raw(""));
+            lines.push(Line::from(error.as_str()).red());
+        }
+        let paragraph = Paragraph::new(lines)
+            .block(block)
+            .cyan()
+            .wrap(Wrap { trim: true });
+        Clear.render(popup_area, buf);
+        paragraph.render(popup_area, buf);
+    }
+}
+
+/// First line of the invoice popup, it depends on how we became the buyer
+pub fn invoice_intro(trade: Option<&Trade>) -> &'static str {
+    match trade {
+        Some(Trade {
+            role: Role::Buyer,
+            maker: true,
+            ..
+        }) => "🧌 Your buying order was taken, please use a fiat payment processor that allows you to send the money immediately and in which there is no risk of freezing funds.",
+        Some(Trade {
+            role: Role::Buyer,
+            maker: false,
+            ..
+        }) => "🧌 You took this selling order, please use a fiat payment processor that allows you to send the money immediately and in which there is no risk of freezing funds.",
+        _ => "🧌 You are the buyer of this order, please use a fiat payment processor that allows you to send the money immediately and in which there is no risk of freezing funds.",
+    }
+}
+
+/// Describes what a message from Mostro about one of our trades means
+pub fn reply_text(action: &Action) -> String {
+    match action {
+        Action::FiatSentOk => "Mostro told the seller you sent the fiat".to_string(),
+        Action::HoldInvoicePaymentSettled => "The sats were released to the buyer".to_string(),
+        Action::Released => "The seller released the sats".to_string(),
+        Action::PurchaseCompleted => "The buyer received the sats, trade completed".to_string(),
+        Action::Rate => "Mostro asks you to rate your counterparty".to_string(),
+        Action::CantDo => "Mostro can't do that".to_string(),
+        Action::PaymentFailed => "Mostro couldn't pay your invoice, it will try again".to_string(),
+        Action::InvoiceUpdated => "Mostro got your new invoice".to_string(),
+        Action::CooperativeCancelInitiatedByYou => {
+            "You asked to cancel, waiting for your counterparty to agree".to_string()
+        }
+        Action::CooperativeCancelInitiatedByPeer => {
+            "Your counterparty asked to cancel the trade".to_string()
+        }
+        Action::CooperativeCancelAccepted => "Both agreed, the trade was canceled".to_string(),
+        Action::Canceled => "The order was canceled".to_string(),
+        Action::DisputeInitiatedByYou => "You opened a dispute".to_string(),
+        Action::DisputeInitiatedByPeer => "Your counterparty opened a dispute".to_string(),
+        Action::AdminTookDispute => "A solver took the dispute and will contact you".to_string(),
+        Action::AdminSettled => {
+            "The solver settled the dispute, the buyer gets the sats".to_string()
+        }
+        Action::AdminCanceled => {
+            "The solver canceled the order, the sats go back to the seller".to_string()
+        }
+        action => format!("Mostro sent {}", action),
+    }
+}
+
+/// Readable reason of an error answer from Mostro, None if it isn't one
+pub fn error_text(action: &Action, content: &Option<Content>) -> Option<String> {
+    let text = match action {
+        Action::CantDo => match content {
+            Some(Content::TextMessage(reason)) => format!("Mostro can't do that: {}", reason),
+            _ => "Mostro can't do that".to_string(),
+        },
+        Action::NotAllowedByStatus => "Not allowed in the current status of the order".to_string(),
+        Action::OutOfRangeFiatAmount => "The fiat amount is out of the order range".to_string(),
+        Action::OutOfRangeSatsAmount => "The sats amount is out of the allowed range".to_string(),
+        Action::InvalidSatsAmount => "The sats amount is not valid".to_string(),
+        Action::IncorrectInvoiceAmount => "The invoice amount is not correct".to_string(),
+        Action::IsNotYourOrder => "This order is not yours".to_string(),
+        Action::IsNotYourDispute => "This dispute is not yours".to_string(),
+        Action::NotFound => "Mostro can't find the order".to_string(),
+        _ => return None,
+    };
+
+    Some(text)
+}